@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nvcap_calculator_fuzz::bit;
+
+// The BIT is found by searching the whole ROM, so its header and entries can end up anywhere
+fuzz_target!(|rom: &[u8]| {
+    let bit = match bit::parse_bit(rom) {
+        Some(bit) => bit,
+        None => return,
+    };
+
+    assert!(bit.offset + bit.header_size <= rom.len() || bit.entries.is_empty());
+    assert!(bit.entries.is_empty() || bit.offset + bit.table_size() <= rom.len());
+});
@@ -0,0 +1,33 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nvcap_calculator_fuzz::nvidia::{self, DcbEntry};
+use nvcap_calculator_fuzz::script;
+use nvcap_calculator_fuzz::util;
+
+// ROMs come from forum posts, so nothing in them can be trusted. Any panic
+// (including an out of bounds read) is a bug.
+fuzz_target!(|rom: &[u8]| {
+    let vbios = match nvidia::parse_rom(rom) {
+        Ok(vbios) => vbios,
+        Err(_) => return,
+    };
+
+    for entry in &vbios.dcb_entries {
+        assert!(entry.offset + 4 <= rom.len());
+        assert_eq!(entry.encode(), util::read_uint_32_le(rom, entry.offset));
+        assert_eq!(DcbEntry::decode(entry.encode(), entry.entry, entry.offset), *entry);
+    }
+
+    for display in &vbios.displays {
+        assert!(!display.dcb_entries.is_empty());
+        assert!(display.dcb_entries.iter().all(|idx| vbios.dcb_entries.iter().any(|entry| entry.entry == *idx)));
+    }
+
+    // Script pointers are just as untrusted as the rest
+    for script in script::find_scripts(&vbios) {
+        for instruction in &script.instructions {
+            assert!(instruction.offset + instruction.len <= rom.len());
+            script::format_instruction(rom, instruction);
+        }
+    }
+});
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::Path;
+use nvcap_calculator_fuzz::test_support::*;
+
+// Writes a starting corpus for the parse_rom and parse_bit targets, run from the fuzz directory:
+// cargo run --bin seed_corpus
+fn main() {
+    let dir = Path::new("corpus/parse_rom");
+    fs::create_dir_all(dir).unwrap();
+
+    let desktop = RomBuilder::new()
+        .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+        .entry(dcb_entry(CRT, 0, 3, 0, 0))
+        .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+        .entry(dcb_entry(CRT, 2, 3, 2, 2))
+        .entry(dcb_entry(TV, 0xf, 3, 3, 3));
+
+    let laptop = RomBuilder::new()
+        .pci(0x10de, 0x0609)
+        .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+        .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+        .entry(dcb_entry(CRT, 1, 3, 1, 1))
+        .entry(dcb_entry(TV, 0xf, 3, 2, 2))
+        .skip()
+        .eol();
+
+    let dcb_30 = RomBuilder::new()
+        .no_pci()
+        .dcb_offset(0x5a0)
+        .version(0x30)
+        .header_size(0x16)
+        .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+        .entry(dcb_entry(TMDS, 1, 3, 1, 1));
+
+    let full = (0..16).fold(RomBuilder::new(), |rom, i| rom.entry(dcb_entry(TMDS, i, 3, i, i)));
+
+    let mut seeds: Vec<(&str, Vec<u8>)> = vec![
+        ("desktop", desktop.build()),
+        ("laptop", laptop.build()),
+        ("dcb_30", dcb_30.build()),
+        ("full", full.build()),
+        ("bmp", RomBuilder::new().bmp(5, 0x25).entry(dcb_entry(TMDS, 0, 3, 0, 0)).build()),
+        ("bad_signature", RomBuilder::new().signature(0).entry(dcb_entry(CRT, 0, 3, 0, 0)).build()),
+    ];
+
+    // Entries running off the end of the ROM
+    let mut truncated = desktop.build();
+    truncated.truncate(0x120);
+    seeds.push(("truncated", truncated));
+
+    for (name, rom) in seeds {
+        fs::write(dir.join(name), rom).unwrap();
+    }
+
+    let dir = Path::new("corpus/parse_bit");
+    fs::create_dir_all(dir).unwrap();
+
+    let bit = RomBuilder::new()
+        .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+        .bit_entry(b'I', 1, &[0; 0x10])
+        .bit_entry(b'i', 2, &[0; 4]);
+    fs::write(dir.join("bit"), bit.build()).unwrap();
+}
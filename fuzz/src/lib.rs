@@ -0,0 +1,23 @@
+// The calculator is a single binary, so build the parser modules it uses straight from its sources
+#![allow(dead_code)]
+
+#[path = "../../src/util.rs"]
+pub mod util;
+#[path = "../../src/edid.rs"]
+pub mod edid;
+#[path = "../../src/export.rs"]
+pub mod export;
+#[path = "../../src/gpus.rs"]
+pub mod gpus;
+#[path = "../../src/bit.rs"]
+pub mod bit;
+#[path = "../../src/bmp.rs"]
+pub mod bmp;
+#[path = "../../src/lvds.rs"]
+pub mod lvds;
+#[path = "../../src/nvidia.rs"]
+pub mod nvidia;
+#[path = "../../src/script.rs"]
+pub mod script;
+#[path = "../../src/test_support.rs"]
+pub mod test_support;
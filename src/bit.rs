@@ -0,0 +1,155 @@
+use crate::util;
+
+// https://nvidia.github.io/open-gpu-doc/BIOS-Information-Table/BIOS-Information-Table.html
+// BIT ("BIOS Information Table") is how newer VBIOSes point at everything that isn't the DCB
+const BIT_SIGNATURE: &[u8] = b"\xff\xb8BIT\x00";
+
+// BIT header offsets
+const BIT_VERSION_OFFSET: usize     = 0x6;
+const BIT_HEADER_SIZE_OFFSET: usize = 0x8;
+const BIT_ENTRY_SIZE_OFFSET: usize  = 0x9;
+const BIT_ENTRY_COUNT_OFFSET: usize = 0xa;
+const BIT_HEADER_MIN_SIZE: usize    = 0xc;
+
+// BIT entry offsets
+const BIT_ENTRY_ID_OFFSET: usize      = 0x0;
+const BIT_ENTRY_VERSION_OFFSET: usize = 0x1;
+const BIT_ENTRY_SIZE_FIELD: usize     = 0x2;
+const BIT_ENTRY_POINTER_FIELD: usize  = 0x4;
+const BIT_ENTRY_MIN_SIZE: usize       = 0x6;
+
+pub struct BitEntry {
+    pub id: u8,
+    pub version: u8,
+    // Size and location of the data the entry points at
+    pub size: usize,
+    pub pointer: usize,
+}
+
+pub struct Bit {
+    pub offset: usize,
+    pub version: u16,
+    pub header_size: usize,
+    pub entry_size: usize,
+    pub entries: Vec<BitEntry>,
+}
+
+impl Bit {
+    // Header and entries, ie. for marking them in the hex viewer
+    pub fn table_size(&self) -> usize {
+        self.header_size + self.entries.len() * self.entry_size
+    }
+}
+
+// What each BIT entry points at, as far as nouveau knows
+pub fn bit_id_to_string(id: u8) -> &'static str {
+    match id {
+        b'C' => "Clock",
+        b'D' => "Flat panel",
+        b'I' => "Init tables",
+        b'L' => "LVDS",
+        b'M' => "Memory",
+        b'P' => "Performance",
+        b'T' => "TMDS",
+        b'U' => "Display scripts",
+        b'd' => "DisplayPort",
+        b'i' => "BIOS info",
+        b'p' => "Falcon",
+        _ => "Unknown",
+    }
+}
+
+pub fn find_signature(rom: &[u8], signature: &[u8]) -> Option<usize> {
+    if signature.is_empty() {
+        return None;
+    }
+
+    rom.windows(signature.len()).position(|window| window == signature)
+}
+
+pub fn parse_bit(rom: &[u8]) -> Option<Bit> {
+    let offset = find_signature(rom, BIT_SIGNATURE)?;
+    if offset + BIT_HEADER_MIN_SIZE > rom.len() {
+        return None;
+    }
+
+    let header_size = rom[offset + BIT_HEADER_SIZE_OFFSET] as usize;
+    let entry_size = rom[offset + BIT_ENTRY_SIZE_OFFSET] as usize;
+    let count = rom[offset + BIT_ENTRY_COUNT_OFFSET] as usize;
+    if header_size < BIT_HEADER_MIN_SIZE || entry_size < BIT_ENTRY_MIN_SIZE {
+        return None;
+    }
+
+    let mut entries: Vec<BitEntry> = vec![];
+    for i in 0..count {
+        let entry = offset + header_size + i * entry_size;
+        if entry + entry_size > rom.len() {
+            break;
+        }
+
+        entries.push(BitEntry {
+            id: rom[entry + BIT_ENTRY_ID_OFFSET],
+            version: rom[entry + BIT_ENTRY_VERSION_OFFSET],
+            size: util::read_uint_16_le(rom, entry + BIT_ENTRY_SIZE_FIELD) as usize,
+            pointer: util::read_uint_16_le(rom, entry + BIT_ENTRY_POINTER_FIELD) as usize,
+        });
+    }
+
+    Some(Bit {
+        offset,
+        version: util::read_uint_16_le(rom, offset + BIT_VERSION_OFFSET),
+        header_size,
+        entry_size,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn parses_bit_entries() {
+        let rom = RomBuilder::new()
+            .bit_entry(b'I', 1, &[0xaa; 0x10])
+            .bit_entry(b'i', 2, &[0xbb; 4])
+            .build();
+        let bit = parse_bit(&rom).unwrap();
+
+        assert_eq!(bit.version, 0x0100);
+        assert_eq!(bit.entries.len(), 2);
+
+        let init = &bit.entries[0];
+        assert_eq!((init.id, init.version, init.size), (b'I', 1, 0x10));
+        assert_eq!(&rom[init.pointer..init.pointer + init.size], &[0xaa; 0x10]);
+        assert_eq!(bit.entries[1].id, b'i');
+    }
+
+    #[test]
+    fn roms_without_bit() {
+        assert!(parse_bit(&RomBuilder::new().build()).is_none());
+
+        // Signature right at the end of the ROM, with no room for the rest of the header
+        let mut rom = RomBuilder::new().build();
+        let end = rom.len() - BIT_SIGNATURE.len();
+        rom[end..].copy_from_slice(BIT_SIGNATURE);
+        assert!(parse_bit(&rom).is_none());
+    }
+
+    #[test]
+    fn truncated_bit_table_keeps_whole_entries() {
+        let mut rom = RomBuilder::new().bit_entry(b'I', 1, &[0; 4]).build();
+        let bit = parse_bit(&rom).unwrap();
+
+        // Claim more entries than fit in the ROM
+        rom[bit.offset + BIT_ENTRY_COUNT_OFFSET] = 0xff;
+        let bit = parse_bit(&rom).unwrap();
+        assert!(bit.offset + bit.table_size() <= rom.len());
+
+        // Entries padded out past the end of the ROM
+        rom[bit.offset + BIT_ENTRY_SIZE_OFFSET] = 0xff;
+        let bit = parse_bit(&rom).unwrap();
+        assert!(bit.offset + bit.table_size() <= rom.len());
+    }
+}
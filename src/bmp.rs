@@ -0,0 +1,154 @@
+use crate::bit;
+use crate::util;
+
+// BMP is the table older (GeForce 7 and earlier) VBIOSes use before BIT took over.
+// Offsets and lengths follow nouveau's parse_bmp_structure.
+const BMP_SIGNATURE: &[u8] = b"\xff\x7fNV\x00";
+
+// BMP offsets
+const BMP_VERSION_MAJOR_OFFSET: usize = 0x5;
+const BMP_VERSION_MINOR_OFFSET: usize = 0x6;
+const BMP_BIOS_VERSION_OFFSET: usize  = 0xa;
+// From BMP 4.x, older versions keep the I2C indices at a fixed address
+const BMP_I2C_OFFSET: usize           = 54;
+const BMP_LEGACY_I2C_ADDR: usize      = 0x48;
+pub const BMP_INIT_SCRIPT_TABLE_OFFSET: usize = 75;
+pub const BMP_FP_TABLE_OFFSET: usize          = 105;
+pub const BMP_FP_XLATE_TABLE_OFFSET: usize    = 107;
+pub const BMP_LVDS_MANUFACTURER_OFFSET: usize = 117;
+
+pub struct Bmp {
+    pub offset: usize,
+    pub version_major: u8,
+    pub version_minor: u8,
+    // How much of the structure this version has, according to nouveau
+    pub length: usize,
+    // ie. 05.72.22.24
+    pub bios_version: [u8; 4],
+    // I2C port indices for the CRT, TV and panel outputs
+    pub i2c_offset: usize,
+    pub i2c_crt: u8,
+    pub i2c_tv: u8,
+    pub i2c_panel: u8,
+    // None if this version doesn't have the pointer, or it is 0
+    pub init_script_table: Option<usize>,
+    pub fp_table: Option<usize>,
+    pub fp_xlate_table: Option<usize>,
+    pub lvds_manufacturer_table: Option<usize>,
+}
+
+impl Bmp {
+    pub fn bios_version_string(&self) -> String {
+        self.bios_version.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(".")
+    }
+}
+
+fn bmp_length(major: u8, minor: u8) -> Option<usize> {
+    match (major, minor) {
+        (1, _) => Some(44),
+        (2, _) => Some(48),
+        (3, _) => Some(54),
+        (4, _) | (5, 0) => Some(62),
+        (5, 0x01..=0x05) => Some(67),
+        (5, 0x06..=0x0f) => Some(75),
+        (5, 0x10) => Some(89),
+        (5, 0x11..=0x13) => Some(118),
+        (5, 0x14..=0x23) => Some(123),
+        (5, 0x24..=0x26) => Some(144),
+        (5, _) => Some(158),
+        _ => None,
+    }
+}
+
+fn bmp_pointer(rom: &[u8], offset: usize, length: usize, field: usize) -> Option<usize> {
+    if length < field + 2 || offset + field + 2 > rom.len() {
+        return None;
+    }
+
+    match util::read_uint_16_le(rom, offset + field) {
+        0 => None,
+        pointer => Some(pointer as usize),
+    }
+}
+
+pub fn parse_bmp(rom: &[u8]) -> Option<Bmp> {
+    let offset = bit::find_signature(rom, BMP_SIGNATURE)?;
+    if offset + BMP_BIOS_VERSION_OFFSET + 4 > rom.len() {
+        return None;
+    }
+
+    let version_major = rom[offset + BMP_VERSION_MAJOR_OFFSET];
+    let version_minor = rom[offset + BMP_VERSION_MINOR_OFFSET];
+
+    // Version 0 has nothing useful, and nouveau has never seen a version 6
+    if (version_major < 5 && version_minor != 1) || version_major > 5 {
+        return None;
+    }
+
+    let length = bmp_length(version_major, version_minor)?;
+
+    let i2c_offset = if length > 61 { offset + BMP_I2C_OFFSET } else { BMP_LEGACY_I2C_ADDR };
+    let i2c = |index: usize| rom.get(i2c_offset + index).copied().unwrap_or(0xff);
+
+    let mut bios_version = [0u8; 4];
+    bios_version.copy_from_slice(&rom[offset + BMP_BIOS_VERSION_OFFSET..offset + BMP_BIOS_VERSION_OFFSET + 4]);
+    bios_version.reverse();
+
+    Some(Bmp {
+        offset,
+        version_major,
+        version_minor,
+        length,
+        bios_version,
+        i2c_offset,
+        i2c_crt: i2c(0),
+        i2c_tv: i2c(1),
+        i2c_panel: i2c(2),
+        init_script_table: bmp_pointer(rom, offset, length, BMP_INIT_SCRIPT_TABLE_OFFSET),
+        fp_table: bmp_pointer(rom, offset, length, BMP_FP_TABLE_OFFSET),
+        fp_xlate_table: bmp_pointer(rom, offset, length, BMP_FP_XLATE_TABLE_OFFSET),
+        lvds_manufacturer_table: bmp_pointer(rom, offset, length, BMP_LVDS_MANUFACTURER_OFFSET),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn parses_bmp() {
+        let rom = RomBuilder::new()
+            .bmp(5, 0x25)
+            .bmp_pointer(BMP_FP_TABLE_OFFSET, &[0xaa; 8])
+            .build();
+        let bmp = parse_bmp(&rom).unwrap();
+
+        assert_eq!((bmp.version_major, bmp.version_minor, bmp.length), (5, 0x25, 144));
+        assert_eq!(bmp.bios_version_string(), "05.72.22.24");
+        assert_eq!(bmp.i2c_offset, bmp.offset + BMP_I2C_OFFSET);
+        assert_eq!((bmp.i2c_crt, bmp.i2c_tv, bmp.i2c_panel), (0, 1, 2));
+
+        let fp_table = bmp.fp_table.unwrap();
+        assert_eq!(&rom[fp_table..fp_table + 8], &[0xaa; 8]);
+        assert!(bmp.lvds_manufacturer_table.is_none());
+    }
+
+    #[test]
+    fn older_versions_have_fewer_pointers() {
+        let rom = RomBuilder::new().bmp(5, 0x10).bmp_pointer(BMP_FP_TABLE_OFFSET, &[0; 8]).build();
+        let bmp = parse_bmp(&rom).unwrap();
+        assert_eq!(bmp.length, 89);
+        assert!(bmp.fp_table.is_none());
+
+        let bmp = parse_bmp(&RomBuilder::new().bmp(3, 1).build()).unwrap();
+        assert_eq!(bmp.i2c_offset, BMP_LEGACY_I2C_ADDR);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(parse_bmp(&RomBuilder::new().bmp(6, 0).build()).is_none());
+        assert!(parse_bmp(&RomBuilder::new().bmp(0, 0).build()).is_none());
+        assert!(parse_bmp(&RomBuilder::new().build()).is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use colored::*;
+use crate::nvidia::{self, DcbEntry, Vbios, NVCAP_SIZE};
+use crate::util::Display;
+
+fn entry_fields(entry: &DcbEntry) -> Vec<(&'static str, String)> {
+    vec![
+        ("Type", format!("{} ({:#x})", nvidia::dcb_type_to_string(entry.entry_type), entry.entry_type)),
+        ("EdidPort", entry.edid_port.to_string()),
+        ("Head", entry.head_bitmask.to_string()),
+        ("Connector", entry.con.to_string()),
+        ("Bus", entry.bus.to_string()),
+        ("Loc", entry.loc.to_string()),
+        ("BDR", entry.bdr.to_string()),
+        ("BBDR", entry.bbdr.to_string()),
+        ("Resources", entry.output_resources.to_string()),
+        ("Virtual", entry.entry_is_virtual.to_string()),
+        ("Reserved", entry.reserved.to_string()),
+    ]
+}
+
+fn describe_entry(entry: &DcbEntry) -> String {
+    entry_fields(entry).iter()
+        .map(|(name, value)| format!("{} {}", name, value))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn describe_display(display: &Display) -> String {
+    let entries: Vec<String> = display.dcb_entries.iter().map(|entry| format!("{:#x}", entry)).collect();
+    format!("{:?} (DCB entries {}, heads {:#x})", display.disp_type, entries.join(", "), display.head_bitmask)
+}
+
+fn print_changed(name: &str, old: &str, new: &str) {
+    println!("    {} {} -> {}", format!("{}:", name).green(), old.red(), new.green());
+}
+
+// Pairs up old and new indexes. Identical items are matched first, then ones that are
+// `similar`; whatever is left was removed or added. Sorted by old index, added ones last.
+fn pair_up(old_len: usize, new_len: usize, identical: impl Fn(usize, usize) -> bool,
+           similar: impl Fn(usize, usize) -> bool) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut pairs: Vec<(Option<usize>, Option<usize>)> = vec![];
+    let mut unmatched_old: Vec<usize> = vec![];
+    let mut unmatched_new: Vec<usize> = (0..new_len).collect();
+
+    for i in 0..old_len {
+        match unmatched_new.iter().position(|&j| identical(i, j)) {
+            Some(pos) => pairs.push((Some(i), Some(unmatched_new.remove(pos)))),
+            None => unmatched_old.push(i),
+        }
+    }
+
+    for i in unmatched_old {
+        match unmatched_new.iter().position(|&j| similar(i, j)) {
+            Some(pos) => pairs.push((Some(i), Some(unmatched_new.remove(pos)))),
+            None => pairs.push((Some(i), None)),
+        }
+    }
+
+    pairs.extend(unmatched_new.into_iter().map(|j| (None, Some(j))));
+    pairs.sort_by_key(|&(i, j)| (i.unwrap_or(usize::MAX), j));
+    pairs
+}
+
+// Entries with the same bits are matched first, then ones with the same type, bus and connector,
+// so an entry inserted in the middle of the DCB doesn't make every later one look changed
+fn match_entries(old: &[DcbEntry], new: &[DcbEntry]) -> Vec<(Option<usize>, Option<usize>)> {
+    let output = |entry: &DcbEntry| (entry.entry_type, entry.bus, entry.con);
+    pair_up(old.len(), new.len(),
+        |i, j| old[i].encode() == new[j].encode(),
+        |i, j| output(&old[i]) == output(&new[j]))
+}
+
+fn diff_dcb_entries(old: &[DcbEntry], new: &[DcbEntry]) -> usize {
+    let mut count = 0;
+
+    for pair in match_entries(old, new) {
+        match pair {
+            (Some(i), Some(j)) => {
+                let (old_entry, new_entry) = (&old[i], &new[j]);
+                let changes: Vec<(&str, String, String)> = entry_fields(old_entry).into_iter()
+                    .zip(entry_fields(new_entry))
+                    .filter(|(old_field, new_field)| old_field.1 != new_field.1)
+                    .map(|((name, old_value), (_, new_value))| (name, old_value, new_value))
+                    .collect();
+
+                if changes.is_empty() {
+                    continue;
+                }
+
+                let label = if old_entry.entry == new_entry.entry {
+                    format!("{:#x}", old_entry.entry)
+                } else {
+                    format!("{:#x} (now {:#x})", old_entry.entry, new_entry.entry)
+                };
+                println!("{} {}", "Changed DCB Entry".yellow(), label);
+                for (name, old_value, new_value) in changes {
+                    print_changed(name, &old_value, &new_value);
+                }
+            }
+            (Some(i), None) => {
+                println!("{} {:#x}", "Removed DCB Entry".red(), old[i].entry);
+                println!("    {}", describe_entry(&old[i]));
+            }
+            (None, Some(j)) => {
+                println!("{} {:#x}", "Added DCB Entry".green(), new[j].entry);
+                println!("    {}", describe_entry(&new[j]));
+            }
+            (None, None) => continue,
+        }
+
+        count += 1;
+    }
+
+    count
+}
+
+// What a display is made of, regardless of where its entries sit in the DCB
+fn display_contents(vbios: &Vbios, display: &Display) -> Vec<u32> {
+    let mut contents: Vec<u32> = vbios.dcb_entries.iter()
+        .filter(|entry| display.dcb_entries.contains(&entry.entry))
+        .map(|entry| entry.encode())
+        .collect();
+
+    contents.sort_unstable();
+    contents
+}
+
+// Bus and connector of a display's first entry, the physical output it drives
+fn display_output(vbios: &Vbios, display: &Display) -> Option<(u32, u32)> {
+    vbios.dcb_entries.iter()
+        .find(|entry| display.dcb_entries.contains(&entry.entry))
+        .map(|entry| (entry.bus, entry.con))
+}
+
+// Displays with identical entries are matched first, then ones driving the same bus and connector
+fn match_displays(old: &Vbios, new: &Vbios) -> Vec<(Option<usize>, Option<usize>)> {
+    pair_up(old.displays.len(), new.displays.len(),
+        |i, j| display_contents(old, &old.displays[i]) == display_contents(new, &new.displays[j]),
+        |i, j| {
+            let output = display_output(old, &old.displays[i]);
+            output.is_some() && display_output(new, &new.displays[j]) == output
+        })
+}
+
+fn diff_displays(old: &Vbios, new: &Vbios) -> usize {
+    let mut count = 0;
+
+    for pair in match_displays(old, new) {
+        match pair {
+            (Some(i), Some(j)) => {
+                let (old_disp, new_disp) = (&old.displays[i], &new.displays[j]);
+                if old_disp.disp_type == new_disp.disp_type && old_disp.head_bitmask == new_disp.head_bitmask
+                    && display_contents(old, old_disp) == display_contents(new, new_disp) {
+                    continue;
+                }
+
+                let label = if i == j { format!("({})", i + 1) } else { format!("({}, now ({}))", i + 1, j + 1) };
+                println!("{} {}", "Changed Display".yellow(), label);
+                print_changed("Display", &describe_display(old_disp), &describe_display(new_disp));
+            }
+            (Some(i), None) => {
+                println!("{} ({}) {}", "Removed Display".red(), i + 1, describe_display(&old.displays[i]));
+            }
+            (None, Some(j)) => {
+                println!("{} ({}) {}", "Added Display".green(), j + 1, describe_display(&new.displays[j]));
+            }
+            (None, None) => continue,
+        }
+
+        count += 1;
+    }
+
+    count
+}
+
+// Show everything that differs between two ROMs which could affect display routing.
+// `old_nvcap` and `new_nvcap` are what this tool would suggest for each ROM.
+pub fn print_diff(old_name: &str, old: &Vbios, old_nvcap: &[u8; NVCAP_SIZE],
+                  new_name: &str, new: &Vbios, new_nvcap: &[u8; NVCAP_SIZE]) {
+    println!("{} {} ({:#010x})", "Old ROM:".red(), old_name, old.crc32);
+    println!("{} {} ({:#010x})", "New ROM:".green(), new_name, new.crc32);
+
+    let pci_id = |vbios: &Vbios| match &vbios.pci {
+        Some(pci) => format!("{:04x}:{:04x}", pci.vendor_id, pci.device_id),
+        None => "none".to_owned(),
+    };
+
+    if pci_id(old) != pci_id(new) {
+        println!("{} the ROMs are for different devices ({} and {})", "Warning:".yellow(),
+            pci_id(old), pci_id(new));
+    }
+
+    if old.crc32 == new.crc32 {
+        println!("{}", "The ROMs are identical".green());
+        return;
+    }
+
+    println!();
+    println!("{}", "DCB Entries".cyan());
+    if diff_dcb_entries(&old.dcb_entries, &new.dcb_entries) == 0 {
+        println!("No changes");
+    }
+
+    println!();
+    println!("{}", "Displays".cyan());
+    if diff_displays(old, new) == 0 {
+        println!("No changes");
+    }
+
+    println!();
+    println!("{}", "Suggested NVCAP".cyan());
+    if old_nvcap == new_nvcap {
+        println!("No changes: {}", nvidia::format_nvcap_hex(new_nvcap, None));
+    } else {
+        println!("Old: {}", nvidia::format_nvcap_hex(old_nvcap, None));
+        println!("New: {}", nvidia::format_nvcap_hex(new_nvcap, Some(old_nvcap)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvidia::parse_rom;
+    use crate::test_support::*;
+
+    #[test]
+    fn matches_displays_by_contents() {
+        let old = parse_rom(&RomBuilder::new()
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(TV, 2, 3, 2, 2))
+            .build()).unwrap();
+
+        // A panel added at the front, and the TMDS output moved to head 1 only
+        let new = parse_rom(&RomBuilder::new()
+            .entry(dcb_entry(LVDS, 3, 1, 3, 3))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 1, 1, 1))
+            .entry(dcb_entry(TV, 2, 3, 2, 2))
+            .build()).unwrap();
+
+        assert_eq!(match_displays(&old, &new),
+            vec![(Some(0), Some(1)), (Some(1), Some(2)), (Some(2), Some(3)), (None, Some(0))]);
+        assert_eq!(diff_displays(&old, &new), 2);
+        assert_eq!(diff_dcb_entries(&old.dcb_entries, &new.dcb_entries), 2);
+
+        // Only the panel inserted, every other entry just moved down
+        let inserted = parse_rom(&RomBuilder::new()
+            .entry(dcb_entry(LVDS, 3, 1, 3, 3))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(TV, 2, 3, 2, 2))
+            .build()).unwrap();
+        assert_eq!(match_entries(&old.dcb_entries, &inserted.dcb_entries),
+            vec![(Some(0), Some(1)), (Some(1), Some(2)), (Some(2), Some(3)), (None, Some(0))]);
+        assert_eq!(diff_dcb_entries(&old.dcb_entries, &inserted.dcb_entries), 1);
+
+        assert_eq!(match_displays(&new, &old),
+            vec![(Some(0), None), (Some(1), Some(0)), (Some(2), Some(1)), (Some(3), Some(2))]);
+        assert_eq!(diff_displays(&old, &old), 0);
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use colored::*;
+use crate::gpus::Suggestion;
+use crate::util::{self, Display, NVErrors};
+
+// EDID 1.3/1.4 base block, see the VESA E-EDID standard
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const EDID_BLOCK_SIZE: usize = 128;
+
+// Base block offsets
+const EDID_MANUFACTURER_OFFSET: usize = 0x8;
+const EDID_PRODUCT_OFFSET: usize      = 0xa;
+const EDID_YEAR_OFFSET: usize         = 0x11;
+const EDID_VERSION_OFFSET: usize      = 0x12;
+const EDID_REVISION_OFFSET: usize     = 0x13;
+const EDID_INPUT_OFFSET: usize        = 0x14;
+const EDID_DESCRIPTORS_OFFSET: usize  = 0x36;
+const EDID_DESCRIPTOR_SIZE: usize     = 18;
+const EDID_DESCRIPTOR_COUNT: usize    = 4;
+
+// Video input byte
+const EDID_INPUT_DIGITAL: u8 = 0x80;
+const EDID_DEPTH_SHIFT: u8   = 4;
+const EDID_DEPTH_MASK: u8    = 0x7;
+const EDID_INTERFACE_MASK: u8 = 0xf;
+
+// Display descriptor holding the monitor name
+const EDID_NAME_TAG: u8 = 0xfc;
+
+pub struct Mode {
+    // kHz
+    pub clock: u32,
+    pub width: u16,
+    pub height: u16,
+}
+
+pub struct Edid {
+    // Three letter PNP ID, ie. "SAM"
+    pub manufacturer: String,
+    pub product: u16,
+    pub year: u16,
+    pub version: u8,
+    pub revision: u8,
+    pub name: Option<String>,
+    pub digital: bool,
+    // Only EDID 1.4 has these
+    pub bits_per_color: Option<u8>,
+    pub interface: Option<&'static str>,
+    // First detailed timing, which is the preferred (native) mode
+    pub native: Option<Mode>,
+}
+
+impl Edid {
+    pub fn summary(&self) -> String {
+        let name = self.name.clone().unwrap_or_else(|| format!("{} {:#06x}", self.manufacturer, self.product));
+        let input = match (self.digital, self.interface) {
+            (false, _) => "analog".to_owned(),
+            (true, Some(interface)) => format!("digital ({})", interface),
+            (true, None) => "digital".to_owned(),
+        };
+
+        let depth = match self.bits_per_color {
+            Some(bpc) => format!(", {} bpc", bpc),
+            None => "".to_owned(),
+        };
+
+        let native = match &self.native {
+            Some(mode) => format!(", {}x{} at {:.2} MHz", mode.width, mode.height, mode.clock as f32 / 1000.0),
+            None => "".to_owned(),
+        };
+
+        format!("{} (EDID {}.{}, {}), {}{}{}", name, self.version, self.revision, self.year, input, depth, native)
+    }
+}
+
+fn decode_manufacturer(id: u16) -> String {
+    // Three 5 bit letters, 1 is 'A'
+    [10, 5, 0].iter()
+        .map(|shift| match (id >> shift) & 0x1f {
+            letter @ 1..=26 => (b'A' + letter as u8 - 1) as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+fn decode_depth(depth: u8) -> Option<u8> {
+    match depth {
+        1..=6 => Some(4 + depth * 2),
+        _ => None,
+    }
+}
+
+fn decode_interface(interface: u8) -> Option<&'static str> {
+    match interface {
+        1 => Some("DVI"),
+        2 | 3 => Some("HDMI"),
+        4 => Some("MDDI"),
+        5 => Some("DisplayPort"),
+        _ => None,
+    }
+}
+
+fn decode_timing(descriptor: &[u8]) -> Option<Mode> {
+    let clock = util::read_uint_16_le(descriptor, 0) as u32 * 10;
+    if clock == 0 {
+        return None;
+    }
+
+    Some(Mode {
+        clock,
+        width: descriptor[2] as u16 | ((descriptor[4] as u16 & 0xf0) << 4),
+        height: descriptor[5] as u16 | ((descriptor[7] as u16 & 0xf0) << 4),
+    })
+}
+
+fn decode_name(descriptor: &[u8]) -> Option<String> {
+    if descriptor[0..3] != [0, 0, 0] || descriptor[3] != EDID_NAME_TAG {
+        return None;
+    }
+
+    let text: String = descriptor[5..].iter()
+        .take_while(|&&byte| byte != b'\n')
+        .map(|&byte| byte as char)
+        .collect();
+
+    Some(text.trim().to_owned()).filter(|name| !name.is_empty())
+}
+
+pub fn parse_edid(bytes: &[u8]) -> Option<Edid> {
+    if bytes.len() < EDID_BLOCK_SIZE || bytes[0..8] != EDID_HEADER {
+        return None;
+    }
+
+    let block = &bytes[..EDID_BLOCK_SIZE];
+    if block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+        return None;
+    }
+
+    let version = block[EDID_VERSION_OFFSET];
+    let revision = block[EDID_REVISION_OFFSET];
+    let input = block[EDID_INPUT_OFFSET];
+    let digital = input & EDID_INPUT_DIGITAL != 0;
+    // Bit depth and interface came with EDID 1.4
+    let has_depth = digital && (version, revision) >= (1, 4);
+
+    let descriptors: Vec<&[u8]> = (0..EDID_DESCRIPTOR_COUNT)
+        .map(|i| &block[EDID_DESCRIPTORS_OFFSET + i * EDID_DESCRIPTOR_SIZE..][..EDID_DESCRIPTOR_SIZE])
+        .collect();
+
+    Some(Edid {
+        manufacturer: decode_manufacturer(u16::from_be_bytes([block[EDID_MANUFACTURER_OFFSET], block[EDID_MANUFACTURER_OFFSET + 1]])),
+        product: util::read_uint_16_le(block, EDID_PRODUCT_OFFSET),
+        year: 1990 + block[EDID_YEAR_OFFSET] as u16,
+        version,
+        revision,
+        name: descriptors.iter().find_map(|descriptor| decode_name(descriptor)),
+        digital,
+        bits_per_color: if has_depth { decode_depth((input >> EDID_DEPTH_SHIFT) & EDID_DEPTH_MASK) } else { None },
+        interface: if has_depth { decode_interface(input & EDID_INTERFACE_MASK) } else { None },
+        native: decode_timing(descriptors[0]),
+    })
+}
+
+// ioreg and some tools print EDIDs as hex, ie. "IODisplayEDID" = <00ffffffffffff00...>
+fn decode_hex_edid(bytes: &[u8]) -> Option<Vec<u8>> {
+    let text = String::from_utf8_lossy(bytes);
+    // Skip the other properties in an ioreg dump
+    let text = &text[text.find("EDID").unwrap_or(0)..];
+    let hex = match text.find('<') {
+        Some(start) => text[start + 1..].split('>').next().unwrap_or(""),
+        None => text,
+    };
+
+    util::parse_hex_bytes(hex)
+}
+
+// Takes a raw EDID (ie. /sys/class/drm/*/edid) or one dumped as hex text
+pub fn read_edid(filename: &str) -> Result<Edid, NVErrors> {
+    let bytes = match fs::read(filename) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("{}", "EDID file not found!".red());
+            println!("{}", e);
+            return Err(NVErrors::FileNotFound);
+        }
+    };
+
+    let edid = match bytes.starts_with(&EDID_HEADER) {
+        true => parse_edid(&bytes),
+        false => decode_hex_edid(&bytes).and_then(|bytes| parse_edid(&bytes)),
+    };
+
+    match edid {
+        Some(edid) => Ok(edid),
+        None => {
+            println!("{}", "Not a valid EDID (bad header or checksum)".red());
+            Err(NVErrors::Corrupted)
+        }
+    }
+}
+
+// Going by the internal panel's EDID, if one was attached. What EDID Bitness does isn't
+// documented anywhere, so this is only a hint and never applied: 6 is a guess that the byte
+// takes the panel's bits per color, as the EDID reports it.
+pub fn suggest_edid_bitness(displays: &[Display]) -> Option<Suggestion> {
+    let edid = displays.iter()
+        .filter(|display| display.disp_type.is_internal())
+        .find_map(|display| display.edid.as_ref())?;
+
+    Some(match edid.bits_per_color {
+        Some(6) => Suggestion {
+            value: 6,
+            reason: "The panel's EDID reports 6 bpc (18 bit), a guess worth trying if colors are off".to_owned(),
+        },
+        Some(bpc) => Suggestion {
+            value: 0,
+            reason: format!("The panel's EDID reports {} bpc, the default is fine", bpc),
+        },
+        None => Suggestion {
+            value: 0,
+            reason: "The panel's EDID doesn't report a bit depth, leave it unless colors are off".to_owned(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvidia;
+    use crate::test_support::*;
+    use crate::validate;
+
+    // EDID 1.4 block for a 1920x1080 DisplayPort panel
+    fn edid_block(input: u8) -> Vec<u8> {
+        let mut block = vec![0u8; EDID_BLOCK_SIZE];
+        block[0..8].copy_from_slice(&EDID_HEADER);
+        block[8..10].copy_from_slice(&[0x4c, 0x2d]);
+        block[10..12].copy_from_slice(&0x0c55u16.to_le_bytes());
+        block[EDID_YEAR_OFFSET] = 30;
+        block[EDID_VERSION_OFFSET] = 1;
+        block[EDID_REVISION_OFFSET] = 4;
+        block[EDID_INPUT_OFFSET] = input;
+
+        // 148.5 MHz, 1920x1080
+        block[0x36..0x3e].copy_from_slice(&[0x02, 0x3a, 0x80, 0x18, 0x71, 0x38, 0x2d, 0x40]);
+        block[0x48..0x4d].copy_from_slice(&[0, 0, 0, EDID_NAME_TAG, 0]);
+        block[0x4d..0x5a].copy_from_slice(b"Panel\n       ");
+
+        let sum = block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        block[EDID_BLOCK_SIZE - 1] = 0u8.wrapping_sub(sum);
+        block
+    }
+
+    #[test]
+    fn parses_edid() {
+        let edid = parse_edid(&edid_block(0xa5)).unwrap();
+        assert_eq!(edid.summary(), "Panel (EDID 1.4, 2020), digital (DisplayPort), 8 bpc, 1920x1080 at 148.50 MHz");
+        assert_eq!(edid.manufacturer, "SAM");
+
+        let edid = parse_edid(&edid_block(0x0e)).unwrap();
+        assert!(!edid.digital);
+        assert_eq!((edid.bits_per_color, edid.interface), (None, None));
+
+        let mut block = edid_block(0x95);
+        assert_eq!(parse_edid(&block).unwrap().bits_per_color, Some(6));
+        block[0x20] ^= 1;
+        assert!(parse_edid(&block).is_none());
+    }
+
+    #[test]
+    fn checks_edids_against_displays() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(CRT, 2, 3, 2, 2))
+            .build();
+        let mut vbios = nvidia::parse_rom(&rom).unwrap();
+        assert!(suggest_edid_bitness(&vbios.displays).is_none());
+
+        vbios.displays[0].edid = parse_edid(&edid_block(0x95));
+        vbios.displays[1].edid = parse_edid(&edid_block(0x0e));
+        vbios.displays[2].edid = parse_edid(&edid_block(0x0e));
+        assert_eq!(suggest_edid_bitness(&vbios.displays).unwrap().value, 6);
+
+        // Only the analog monitor on the TMDS output is a problem
+        let issues = validate::check(&vbios.displays, &vbios.dcb_entries, true, &[], &[0], &[1, 2]);
+        let analog: Vec<&str> = issues.iter()
+            .map(|issue| issue.message.as_str())
+            .filter(|message| message.contains("analog"))
+            .collect();
+        assert_eq!(analog.len(), 1);
+        assert!(analog[0].starts_with("Display (2) TMDS is a digital-only output"));
+
+        // Changing how entries are merged keeps the EDIDs with their outputs
+        vbios.set_merge_strategy(nvidia::MergeStrategy::None);
+        assert!(vbios.displays.iter().all(|display| display.edid.is_some()));
+    }
+
+    #[test]
+    fn reads_hex_edids() {
+        let hex: String = edid_block(0xa5).iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text = format!("    | |   \"IOFBConfig\" = <00>\n    | |   \"IODisplayEDID\" = <{}>\n", hex);
+        assert_eq!(decode_hex_edid(text.as_bytes()), Some(edid_block(0xa5)));
+    }
+}
@@ -0,0 +1,85 @@
+use colored::*;
+use crate::nvidia::NVCAP_SIZE;
+
+// Usual slot for a PCIe graphics card, used until the real path is imported from ioreg
+pub const DEFAULT_DEVICE_PATH: &str = "PciRoot(0x0)/Pci(0x1,0x0)/Pci(0x0,0x0)";
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// plist <data> is base64
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| value | (byte as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(value >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+// Device properties in `section`, ie. DeviceProperties/Add for OpenCore
+fn plist_snippet(section: (&str, &str), device_path: &str, nvcap: &[u8; NVCAP_SIZE]) -> String {
+    [
+        format!("<key>{}</key>", section.0),
+        "<dict>".to_owned(),
+        format!("\t<key>{}</key>", section.1),
+        "\t<dict>".to_owned(),
+        format!("\t\t<key>{}</key>", device_path),
+        "\t\t<dict>".to_owned(),
+        "\t\t\t<key>NVCAP</key>".to_owned(),
+        format!("\t\t\t<data>{}</data>", base64(nvcap)),
+        "\t\t</dict>".to_owned(),
+        "\t</dict>".to_owned(),
+        "</dict>".to_owned(),
+    ].join("\n")
+}
+
+pub fn opencore_snippet(device_path: &str, nvcap: &[u8; NVCAP_SIZE]) -> String {
+    plist_snippet(("DeviceProperties", "Add"), device_path, nvcap)
+}
+
+pub fn clover_snippet(device_path: &str, nvcap: &[u8; NVCAP_SIZE]) -> String {
+    plist_snippet(("Devices", "Properties"), device_path, nvcap)
+}
+
+pub fn print_exports(nvcap: &[u8; NVCAP_SIZE], device_path: Option<&str>) {
+    let path = match device_path {
+        Some(path) => path,
+        None => {
+            println!("{} {} is a guess, import an ioreg dump from the main menu to use the real one",
+                "Device path:".yellow(), DEFAULT_DEVICE_PATH);
+            DEFAULT_DEVICE_PATH
+        }
+    };
+
+    println!();
+    println!("{} (config.plist)", "OpenCore".cyan());
+    println!("{}", opencore_snippet(path, nvcap));
+    println!();
+    println!("{} (config.plist)", "Clover".cyan());
+    println!("{}", clover_snippet(path, nvcap));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+
+        let nvcap = [0x05, 0x01, 0, 0, 0, 0, 0x01, 0, 0x06, 0, 0, 0, 0, 0, 0, 0x0f, 0, 0, 0, 0];
+        assert_eq!(base64(&nvcap), "BQEAAAAAAQAGAAAAAAAADwAAAAA=");
+        assert!(opencore_snippet(DEFAULT_DEVICE_PATH, &nvcap)
+            .contains("<key>PciRoot(0x0)/Pci(0x1,0x0)/Pci(0x0,0x0)</key>"));
+    }
+}
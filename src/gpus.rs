@@ -0,0 +1,251 @@
+use std::fmt;
+use crate::nvidia::{NVCAP_VERSION_LEGACY, NVCAP_VERSION_MODERN};
+
+pub const NVIDIA_VENDOR_ID: u16 = 0x10de;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Architecture {
+    NV4x,   // GeForce 6/7
+    G8x,    // GeForce 8
+    G9x,    // GeForce 9, 8800 GT/GTS 512, MCP7x
+    GT2xx,  // GeForce 200/300, MCP89
+    GF1xx,  // Fermi
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Architecture::NV4x => "NV4x (Curie)",
+            Architecture::G8x => "G8x (Tesla)",
+            Architecture::G9x => "G9x (Tesla)",
+            Architecture::GT2xx => "GT2xx (Tesla)",
+            Architecture::GF1xx => "GF1xx (Fermi)",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+pub struct GpuInfo {
+    pub device_id: u16,
+    pub name: &'static str,
+    pub arch: Architecture,
+    pub mobile: bool,
+    // Chipset GPU (nForce MCP) sharing system memory
+    pub integrated: bool,
+    // Does macOS ship a driver for this GPU at all? Chipset GPUs without QE/CI and
+    // Optimus-only GPUs without display outputs never worked in macOS.
+    pub native: bool,
+}
+
+impl GpuInfo {
+    pub fn class(&self) -> &'static str {
+        match (self.mobile, self.integrated) {
+            (true, true) => "Mobile, Integrated",
+            (true, false) => "Mobile",
+            (false, true) => "Desktop, Integrated",
+            (false, false) => "Desktop",
+        }
+    }
+
+    // Apple's 0x0E/0x0F field F values only show up from the 300 series (GT21x) onwards.
+    // GT200 (GTX 260-285) is the only GT2xx chip older than that.
+    fn is_300_series_or_newer(&self) -> bool {
+        match self.arch {
+            Architecture::GF1xx => true,
+            Architecture::GT2xx => !(0x05e0..=0x05ff).contains(&self.device_id),
+            _ => false,
+        }
+    }
+}
+
+const fn gpu(device_id: u16, name: &'static str, arch: Architecture, mobile: bool, native: bool) -> GpuInfo {
+    GpuInfo { device_id, name, arch, mobile, integrated: false, native }
+}
+
+const fn igp(device_id: u16, name: &'static str, arch: Architecture, mobile: bool, native: bool) -> GpuInfo {
+    GpuInfo { device_id, name, arch, mobile, integrated: true, native }
+}
+
+// Device IDs from https://pci-ids.ucw.cz/read/PC/10de
+// Not meant to be complete, just the GPUs people usually try to get working
+static GPUS: &[GpuInfo] = &[
+    // NV4x
+    gpu(0x0040, "GeForce 6800 Ultra", Architecture::NV4x, false, true),
+    gpu(0x0041, "GeForce 6800", Architecture::NV4x, false, true),
+    gpu(0x00c8, "GeForce Go 6800", Architecture::NV4x, true, true),
+    gpu(0x0091, "GeForce 7800 GTX", Architecture::NV4x, false, true),
+    gpu(0x0092, "GeForce 7800 GT", Architecture::NV4x, false, true),
+    gpu(0x0140, "GeForce 6600 GT", Architecture::NV4x, false, true),
+    gpu(0x0141, "GeForce 6600", Architecture::NV4x, false, true),
+    gpu(0x01d7, "GeForce Go 7300", Architecture::NV4x, true, true),
+    gpu(0x01d8, "GeForce Go 7400", Architecture::NV4x, true, true),
+    igp(0x0240, "GeForce 6150 (C51)", Architecture::NV4x, false, false),
+    gpu(0x0290, "GeForce 7900 GTX", Architecture::NV4x, false, true),
+    gpu(0x0291, "GeForce 7900 GT/GTO", Architecture::NV4x, false, true),
+    gpu(0x0298, "GeForce Go 7900 GS", Architecture::NV4x, true, true),
+    gpu(0x0299, "GeForce Go 7900 GTX", Architecture::NV4x, true, true),
+    gpu(0x0391, "GeForce 7600 GT", Architecture::NV4x, false, true),
+    gpu(0x0393, "GeForce 7300 GT", Architecture::NV4x, false, true),
+    gpu(0x0398, "GeForce Go 7600", Architecture::NV4x, true, true),
+    igp(0x0531, "GeForce 7150M (C67)", Architecture::NV4x, true, false),
+
+    // G8x
+    gpu(0x0191, "GeForce 8800 GTX", Architecture::G8x, false, true),
+    gpu(0x0193, "GeForce 8800 GTS", Architecture::G8x, false, true),
+    gpu(0x0400, "GeForce 8600 GTS", Architecture::G8x, false, true),
+    gpu(0x0402, "GeForce 8600 GT", Architecture::G8x, false, true),
+    gpu(0x0407, "GeForce 8600M GT", Architecture::G8x, true, true),
+    gpu(0x0409, "GeForce 8700M GT", Architecture::G8x, true, true),
+    gpu(0x0421, "GeForce 8500 GT", Architecture::G8x, false, true),
+    gpu(0x0422, "GeForce 8400 GS", Architecture::G8x, false, true),
+    gpu(0x0425, "GeForce 8600M GS", Architecture::G8x, true, true),
+    gpu(0x0426, "GeForce 8400M GT", Architecture::G8x, true, true),
+    gpu(0x0427, "GeForce 8400M GS", Architecture::G8x, true, true),
+    gpu(0x0428, "GeForce 8400M G", Architecture::G8x, true, true),
+
+    // G9x
+    gpu(0x0600, "GeForce 8800 GTS 512", Architecture::G9x, false, true),
+    gpu(0x0609, "GeForce 8800M GTS", Architecture::G9x, true, true),
+    gpu(0x060c, "GeForce 8800M GTX", Architecture::G9x, true, true),
+    gpu(0x0611, "GeForce 8800 GT", Architecture::G9x, false, true),
+    gpu(0x0612, "GeForce 9800 GTX/9800 GTX+", Architecture::G9x, false, true),
+    gpu(0x0614, "GeForce 9800 GT", Architecture::G9x, false, true),
+    gpu(0x0617, "GeForce 9800M GTX", Architecture::G9x, true, true),
+    gpu(0x0622, "GeForce 9600 GT", Architecture::G9x, false, true),
+    gpu(0x0640, "GeForce 9500 GT/GT 120", Architecture::G9x, false, true),
+    gpu(0x0647, "GeForce 9600M GT", Architecture::G9x, true, true),
+    gpu(0x06e4, "GeForce 8400 GS", Architecture::G9x, false, true),
+    gpu(0x06e8, "GeForce 9200M GS", Architecture::G9x, true, true),
+    igp(0x0848, "GeForce 8300 (MCP78)", Architecture::G9x, false, false),
+    igp(0x0849, "GeForce 8200 (MCP78)", Architecture::G9x, false, false),
+    igp(0x0861, "GeForce 9400", Architecture::G9x, false, true),
+    igp(0x0863, "GeForce 9400M", Architecture::G9x, true, true),
+    igp(0x0866, "GeForce 9400M G", Architecture::G9x, true, true),
+
+    // GT2xx
+    gpu(0x05e1, "GeForce GTX 280", Architecture::GT2xx, false, true),
+    gpu(0x05e2, "GeForce GTX 260", Architecture::GT2xx, false, true),
+    gpu(0x05e3, "GeForce GTX 285", Architecture::GT2xx, false, true),
+    gpu(0x05e6, "GeForce GTX 275", Architecture::GT2xx, false, true),
+    igp(0x08a0, "GeForce 320M", Architecture::GT2xx, true, true),
+    gpu(0x0a20, "GeForce GT 220", Architecture::GT2xx, false, true),
+    gpu(0x0a29, "GeForce GT 330M", Architecture::GT2xx, true, true),
+    gpu(0x0a34, "GeForce GT 240M", Architecture::GT2xx, true, true),
+    gpu(0x0a65, "GeForce 210", Architecture::GT2xx, false, true),
+    gpu(0x0a6c, "NVS 3100M", Architecture::GT2xx, true, true),
+    gpu(0x0a75, "GeForce 310M", Architecture::GT2xx, true, true),
+    gpu(0x0ca3, "GeForce GT 240", Architecture::GT2xx, false, true),
+
+    // GF1xx
+    gpu(0x06c0, "GeForce GTX 480", Architecture::GF1xx, false, true),
+    gpu(0x06cd, "GeForce GTX 470", Architecture::GF1xx, false, true),
+    gpu(0x0dc4, "GeForce GTS 450", Architecture::GF1xx, false, true),
+    gpu(0x0dd1, "GeForce GTX 460M", Architecture::GF1xx, true, true),
+    gpu(0x0de1, "GeForce GT 430", Architecture::GF1xx, false, true),
+    gpu(0x0df4, "GeForce GT 540M", Architecture::GF1xx, true, true),
+    gpu(0x0e22, "GeForce GTX 460", Architecture::GF1xx, false, true),
+    gpu(0x104a, "GeForce GT 610", Architecture::GF1xx, false, true),
+    gpu(0x1050, "GeForce GT 520M", Architecture::GF1xx, true, true),
+    gpu(0x1080, "GeForce GTX 580", Architecture::GF1xx, false, true),
+    gpu(0x1081, "GeForce GTX 570", Architecture::GF1xx, false, true),
+    gpu(0x1140, "GeForce GT 620M (GF117, Optimus only)", Architecture::GF1xx, true, false),
+    gpu(0x1200, "GeForce GTX 560 Ti", Architecture::GF1xx, false, true),
+];
+
+pub fn lookup(vendor_id: u16, device_id: u16) -> Option<&'static GpuInfo> {
+    if vendor_id != NVIDIA_VENDOR_ID {
+        return None;
+    }
+
+    GPUS.iter().find(|gpu| gpu.device_id == device_id)
+}
+
+// A default NVCAP value along with why it was picked
+pub struct Suggestion {
+    pub value: u8,
+    pub reason: String,
+}
+
+pub fn suggest_version(gpu: Option<&GpuInfo>) -> Suggestion {
+    match gpu {
+        Some(gpu) if gpu.arch == Architecture::NV4x => Suggestion {
+            value: NVCAP_VERSION_LEGACY,
+            reason: format!("{} GPUs (6000/7000 series) use version 4", gpu.arch),
+        },
+        Some(gpu) => Suggestion {
+            value: NVCAP_VERSION_MODERN,
+            reason: format!("{} GPUs (8000 series and newer) use version 5", gpu.arch),
+        },
+        None => Suggestion {
+            value: NVCAP_VERSION_MODERN,
+            reason: "Unknown GPU, assuming an 8000 series or newer card (version 5)".to_owned(),
+        },
+    }
+}
+
+// https://github.com/acidanthera/WhateverGreen/blob/master/Manual/NVCAP.bt
+pub fn suggest_field_f(gpu: Option<&GpuInfo>) -> Suggestion {
+    let gpu = match gpu {
+        Some(gpu) => gpu,
+        None => return Suggestion {
+            value: 0x0f,
+            reason: "Unknown GPU, assuming a 300 series+ high end card (0x0F)".to_owned(),
+        },
+    };
+
+    if gpu.is_300_series_or_newer() {
+        if gpu.integrated {
+            Suggestion {
+                value: 0x0e,
+                reason: format!("{} is a 300 series+ integrated GPU (MacBook Air/low end)", gpu.name),
+            }
+        } else {
+            Suggestion {
+                value: 0x0f,
+                reason: format!("{} is a 300 series+ dedicated GPU (MacBook Pro/iMac/high end)", gpu.name),
+            }
+        }
+    } else if gpu.mobile {
+        Suggestion {
+            value: 0x0b,
+            reason: format!("{} is a pre-300 series laptop-class GPU", gpu.name),
+        }
+    } else {
+        Suggestion {
+            value: 0x0a,
+            reason: format!("{} is a pre-300 series desktop-class GPU", gpu.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_defaults_by_gpu() {
+        // Device ID, version, field F and whether it counts as 300 series or newer
+        let cases = [
+            (0x0299, 4, 0x0b, false), // GeForce Go 7900 GTX, NV4x
+            (0x0407, 5, 0x0b, false), // GeForce 8600M GT, G8x mobile
+            (0x05e3, 5, 0x0a, false), // GeForce GTX 285, GT200 desktop
+            (0x0a20, 5, 0x0f, true),  // GeForce GT 220, GT21x desktop
+            (0x0a29, 5, 0x0f, true),  // GeForce GT 330M, 300 series mobile
+            (0x08a0, 5, 0x0e, true),  // GeForce 320M, integrated
+            (0x0863, 5, 0x0b, false), // GeForce 9400M, integrated before the 300 series
+            (0x06c0, 5, 0x0f, true),  // GeForce GTX 480, Fermi
+        ];
+
+        for (device_id, version, field_f, newer) in cases {
+            let gpu = lookup(NVIDIA_VENDOR_ID, device_id).unwrap();
+            assert_eq!(suggest_version(Some(gpu)).value, version, "{}", gpu.name);
+            assert_eq!(suggest_field_f(Some(gpu)).value, field_f, "{}", gpu.name);
+            assert_eq!(gpu.is_300_series_or_newer(), newer, "{}", gpu.name);
+        }
+
+        assert_eq!(suggest_version(None).value, 5);
+        assert_eq!(suggest_field_f(None).value, 0x0f);
+        assert!(lookup(0x1002, 0x0407).is_none());
+    }
+}
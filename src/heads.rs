@@ -0,0 +1,246 @@
+use crate::nvidia::{HEAD_0_BITMASK, HEAD_1_BITMASK};
+use crate::util::{Display, DisplayType};
+
+// Past this many displays, trying every layout gets too slow to be worth it
+const MAX_DISPLAYS: usize = 16;
+// Suggested layout plus alternatives
+const MAX_LAYOUTS: usize = 3;
+
+// Score weights
+const PRIORITY_WEIGHT: i32 = 10;
+const SAME_TYPE_PENALTY: i32 = 3;
+const SHARED_PANEL_PENALTY: i32 = 2;
+
+// One possible split of the non-TV displays between head 1 and head 2
+pub struct Layout {
+    pub head_0: Vec<usize>,
+    pub head_1: Vec<usize>,
+    pub score: i32,
+    pub notes: Vec<String>,
+}
+
+impl Layout {
+    fn head_of(&self, disp_idx: usize) -> Option<usize> {
+        if self.head_0.contains(&disp_idx) {
+            Some(0)
+        } else if self.head_1.contains(&disp_idx) {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    fn is_mirror_of(&self, other: &Layout) -> bool {
+        self.head_0 == other.head_1 && self.head_1 == other.head_0
+    }
+}
+
+fn score_layout(displays: &[Display], priorities: &[usize], layout: &mut Layout) {
+    let mut score = 0;
+    let mut notes: Vec<String> = vec![];
+
+    // Each head only drives one output at a time, so the outputs the user cares
+    // about most should be split across heads. Higher priorities weigh more.
+    let count = priorities.len() as i32;
+    for (i, &a) in priorities.iter().enumerate() {
+        for (j, &b) in priorities.iter().enumerate().skip(i + 1) {
+            let weight = PRIORITY_WEIGHT * (2 * count - i as i32 - j as i32);
+            match (layout.head_of(a), layout.head_of(b)) {
+                (Some(head_a), Some(head_b)) if head_a != head_b => {
+                    score += weight;
+                    notes.push(format!("{} and {} can be used at the same time",
+                        displays[a].label(a), displays[b].label(b)));
+                }
+                (Some(head), Some(_)) => {
+                    score -= weight;
+                    notes.push(format!("{} and {} share head {}, only one can be used at a time",
+                        displays[a].label(a), displays[b].label(b), head + 1));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (head, members) in [&layout.head_0, &layout.head_1].iter().enumerate() {
+        for (i, &a) in members.iter().enumerate() {
+            for &b in members.iter().skip(i + 1) {
+                // Two of the same output (ie. two DVI ports, or DVI and HDMI) should go on different heads
+                let (type_a, type_b) = (&displays[a].disp_type, &displays[b].disp_type);
+                if type_a == type_b || (type_a.is_tmds() && type_b.is_tmds()) {
+                    score -= SAME_TYPE_PENALTY;
+                    notes.push(format!("{} and {} are the same kind of output but share head {}",
+                        displays[a].label(a), displays[b].label(b), head + 1));
+                }
+
+                // Keep the internal panel on its own head
+                if displays[a].disp_type.is_internal() || displays[b].disp_type.is_internal() {
+                    score -= SHARED_PANEL_PENALTY;
+                    notes.push(format!("{} and {} share head {} with the internal panel",
+                        displays[a].label(a), displays[b].label(b), head + 1));
+                }
+            }
+        }
+    }
+
+    score -= (layout.head_0.len() as i32 - layout.head_1.len() as i32).abs();
+
+    layout.score = score;
+    layout.notes = notes;
+}
+
+// Displays that can't go in any layout, with the reason why
+fn unplaced(displays: &[Display], candidates: &[usize]) -> Vec<String> {
+    let mut notes: Vec<String> = vec![];
+    for (i, display) in displays.iter().enumerate() {
+        if display.disp_type == DisplayType::TV || candidates.contains(&i) {
+            continue;
+        }
+
+        if display.head_bitmask & (HEAD_0_BITMASK | HEAD_1_BITMASK) == 0 {
+            notes.push(format!("{} supports neither head 1 nor head 2 and was left out", display.label(i)));
+        } else {
+            notes.push(format!("{} was left out, only the first {} displays are laid out",
+                display.label(i), MAX_DISPLAYS));
+        }
+    }
+
+    notes
+}
+
+// Try every valid split of displays between the two heads, best layout first.
+// TV outputs are left out as they go on the TV head.
+pub fn solve(displays: &[Display], priorities: &[usize]) -> Vec<Layout> {
+    let candidates: Vec<usize> = (0..displays.len())
+        .filter(|&i| displays[i].disp_type != DisplayType::TV)
+        .filter(|&i| displays[i].head_bitmask & (HEAD_0_BITMASK | HEAD_1_BITMASK) != 0)
+        .take(MAX_DISPLAYS)
+        .collect();
+    let left_out = unplaced(displays, &candidates);
+
+    // Listing a display twice doesn't make it more important
+    let mut unique_priorities: Vec<usize> = vec![];
+    for &disp_idx in priorities {
+        if !unique_priorities.contains(&disp_idx) {
+            unique_priorities.push(disp_idx);
+        }
+    }
+
+    let mut layouts: Vec<Layout> = vec![];
+
+    // Bit n set means candidate n goes on head 2
+    for split in 0..(1u32 << candidates.len()) {
+        let mut layout = Layout { head_0: vec![], head_1: vec![], score: 0, notes: vec![] };
+        let mut valid = true;
+
+        for (bit, &disp_idx) in candidates.iter().enumerate() {
+            let (head, mask) = if split & (1 << bit) == 0 {
+                (&mut layout.head_0, HEAD_0_BITMASK)
+            } else {
+                (&mut layout.head_1, HEAD_1_BITMASK)
+            };
+
+            if displays[disp_idx].head_bitmask & mask == 0 {
+                valid = false;
+                break;
+            }
+
+            head.push(disp_idx);
+        }
+
+        if valid {
+            score_layout(displays, &unique_priorities, &mut layout);
+            layout.notes.splice(0..0, left_out.iter().cloned());
+            layouts.push(layout);
+        }
+    }
+
+    // Leave head 1 with fewer displays when tied so the primary output gets its own head,
+    // then keep displays in the order the DCB lists them
+    layouts.sort_by(|a, b| b.score.cmp(&a.score)
+        .then(a.head_0.len().cmp(&b.head_0.len()))
+        .then(a.head_0.cmp(&b.head_0)));
+
+    // Swapping heads gives an identical layout on desktop cards, only keep one of them
+    let mut unique: Vec<Layout> = vec![];
+    for layout in layouts {
+        if unique.len() == MAX_LAYOUTS {
+            break;
+        }
+
+        if !unique.iter().any(|other| layout.is_mirror_of(other)) {
+            unique.push(layout);
+        }
+    }
+
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(disp_type: DisplayType, head_bitmask: u32) -> Display {
+        Display {
+            disp_type,
+            dcb_entries: vec![],
+            head_bitmask,
+            merge_reason: String::new(),
+            type_reason: String::new(),
+            edid: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_internal_panel_alone() {
+        let displays = [
+            display(DisplayType::LVDS, 1),
+            display(DisplayType::Analog, 3),
+            display(DisplayType::DVI, 3),
+        ];
+
+        let layouts = solve(&displays, &[]);
+        assert_eq!((layouts[0].head_0.clone(), layouts[0].head_1.clone()), (vec![0], vec![1, 2]));
+        assert!(layouts.len() <= MAX_LAYOUTS);
+    }
+
+    #[test]
+    fn splits_outputs_of_the_same_kind() {
+        let displays = [
+            display(DisplayType::DVI, 3),
+            display(DisplayType::DVI, 3),
+            display(DisplayType::TV, 3),
+        ];
+
+        let layouts = solve(&displays, &[]);
+        assert_eq!((layouts[0].head_0.clone(), layouts[0].head_1.clone()), (vec![0], vec![1]));
+        // Swapped heads are the same layout on a desktop card
+        assert_eq!(layouts.len(), 2);
+    }
+
+    #[test]
+    fn follows_priorities() {
+        let displays = [
+            display(DisplayType::DVI, 3),
+            display(DisplayType::DVI, 3),
+            display(DisplayType::Analog, 3),
+        ];
+
+        // The analog output and the second DVI port should be usable together
+        let layouts = solve(&displays, &[2, 1, 2, 1]);
+        let best = &layouts[0];
+        assert_ne!(best.head_0.contains(&1), best.head_0.contains(&2));
+        assert_eq!(best.notes.iter().filter(|note| note.contains("at the same time")).count(), 1);
+    }
+
+    #[test]
+    fn reports_displays_left_out() {
+        let mut displays = vec![display(DisplayType::Analog, 0)];
+        displays.extend((0..MAX_DISPLAYS + 1).map(|_| display(DisplayType::DVI, 3)));
+
+        let layouts = solve(&displays, &[]);
+        let notes = &layouts[0].notes;
+        assert!(notes[0].starts_with("Display (1) Analog supports neither head"));
+        assert!(notes[1].starts_with(&format!("Display ({}) DVI was left out", MAX_DISPLAYS + 2)));
+        assert_eq!(layouts[0].head_0.len() + layouts[0].head_1.len(), MAX_DISPLAYS);
+    }
+}
@@ -0,0 +1,292 @@
+use colored::*;
+use crate::bit;
+use crate::nvidia::Vbios;
+use crate::util;
+
+const BYTES_PER_LINE: usize = 16;
+const LINES_PER_PAGE: usize = 16;
+const PAGE_SIZE: usize = BYTES_PER_LINE * LINES_PER_PAGE;
+
+// Size of the PCI expansion ROM header, up to and including the PCIR pointer
+const PCI_ROM_HEADER_SIZE: usize = 0x1a;
+
+// Colors cycled through to tell neighbouring regions apart
+const REGION_COLORS: [Color; 4] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Green];
+
+// A range of the ROM we know the meaning of
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub label: String,
+}
+
+fn push_region(regions: &mut Vec<Region>, rom_len: usize, start: usize, len: usize, label: String) {
+    let end = (start + len).min(rom_len);
+    if start < end {
+        regions.push(Region { start, end, label });
+    }
+}
+
+// DCB 4.x tables (I2C, GPIO, connectors) all start with version, header size, count and entry size
+fn push_table(regions: &mut Vec<Region>, rom: &[u8], pointer: Option<usize>, label: &str) {
+    let table = match pointer {
+        Some(table) if table + 3 < rom.len() => table,
+        _ => return,
+    };
+
+    let size = rom[table + 1] as usize + rom[table + 2] as usize * rom[table + 3] as usize;
+    push_region(regions, rom.len(), table, size.max(4), label.to_owned());
+}
+
+// Everything this tool knows how to find in the ROM, sorted by where it starts
+pub fn known_regions(vbios: &Vbios) -> Vec<Region> {
+    let rom = &vbios.rom;
+    let mut regions: Vec<Region> = vec![];
+
+    if let Some(pci) = &vbios.pci {
+        push_region(&mut regions, rom.len(), 0, PCI_ROM_HEADER_SIZE, "PCI ROM header".to_owned());
+        push_region(&mut regions, rom.len(), pci.offset, pci.length, "PCIR".to_owned());
+    }
+
+    if let Some(bit) = &vbios.bit {
+        push_region(&mut regions, rom.len(), bit.offset, bit.header_size,
+            format!("BIT header (version {:#06x})", bit.version));
+        push_region(&mut regions, rom.len(), bit.offset + bit.header_size, bit.table_size() - bit.header_size,
+            "BIT entries".to_owned());
+
+        for entry in bit.entries.iter().filter(|entry| entry.pointer != 0) {
+            push_region(&mut regions, rom.len(), entry.pointer, entry.size, format!("BIT '{}' ({}) version {} data",
+                entry.id as char, bit::bit_id_to_string(entry.id), entry.version));
+        }
+    }
+
+    if let Some(bmp) = &vbios.bmp {
+        push_region(&mut regions, rom.len(), bmp.offset, bmp.length,
+            format!("BMP (version {}.{:x})", bmp.version_major, bmp.version_minor));
+    }
+
+    if let Some(lvds) = &vbios.lvds {
+        push_region(&mut regions, rom.len(), lvds.offset, lvds.header_size + lvds.straps.len() * lvds.record_size,
+            format!("LVDS table (version {:#x})", lvds.version));
+    }
+
+    let dcb = &vbios.dcb;
+    push_region(&mut regions, rom.len(), dcb.offset, dcb.size, "DCB header".to_owned());
+    for entry in &vbios.dcb_entries {
+        push_region(&mut regions, rom.len(), entry.offset, (dcb.entry_size as usize).max(4),
+            format!("DCB entry {:#x}", entry.entry));
+    }
+
+    push_table(&mut regions, rom, dcb.i2c_table, "I2C table");
+    push_table(&mut regions, rom, dcb.gpio_table, "GPIO table");
+    push_table(&mut regions, rom, dcb.connector_table, "Connector table");
+
+    regions.sort_by_key(|region| region.start);
+    regions
+}
+
+// Smallest region holding the byte, so entries inside a table win over the table
+fn region_at(regions: &[Region], offset: usize) -> Option<usize> {
+    (0..regions.len())
+        .filter(|&i| regions[i].start <= offset && offset < regions[i].end)
+        .min_by_key(|&i| regions[i].end - regions[i].start)
+}
+
+// What the user typed to search for, either "text" in quotes or hex bytes
+pub fn parse_pattern(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim();
+    if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+        let text = &input[1..input.len() - 1];
+        return if text.is_empty() { None } else { Some(text.as_bytes().to_vec()) };
+    }
+
+    util::parse_hex_bytes(input)
+}
+
+// Next match at or after `from`, wrapping around to the start of the ROM
+pub fn find_pattern(rom: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    let from = from.min(rom.len());
+    bit::find_signature(&rom[from..], pattern)
+        .map(|pos| pos + from)
+        .or_else(|| bit::find_signature(rom, pattern))
+}
+
+fn print_page(rom: &[u8], regions: &[Region], start: usize, highlight: Option<(usize, usize)>) {
+    for line in (start..(start + PAGE_SIZE).min(rom.len())).step_by(BYTES_PER_LINE) {
+        let end = (line + BYTES_PER_LINE).min(rom.len());
+        let mut hex = String::new();
+        let mut ascii = String::new();
+
+        for (offset, &byte) in rom.iter().enumerate().skip(line).take(BYTES_PER_LINE) {
+            let mut text = format!("{:02x}", byte).normal();
+            if let Some(region) = region_at(regions, offset) {
+                text = text.color(REGION_COLORS[region % REGION_COLORS.len()]);
+            }
+            if let Some((found, len)) = highlight {
+                if found <= offset && offset < found + len {
+                    text = text.reversed();
+                }
+            }
+
+            hex += &format!("{} ", text);
+            ascii.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+        }
+
+        // Keep the ASCII column lined up on the last line
+        hex += &"   ".repeat(BYTES_PER_LINE - (end - line));
+
+        let labels: Vec<&str> = regions.iter()
+            .filter(|region| (line..end).contains(&region.start))
+            .map(|region| region.label.as_str())
+            .collect();
+
+        let labels = if labels.is_empty() { "".to_owned() } else { format!("<- {}", labels.join(", ")) };
+        println!("{} {}|{:<16}| {}", format!("{:08x}", line).bright_blue(), hex, ascii, labels.green());
+    }
+}
+
+fn choose_region(regions: &[Region]) -> Option<usize> {
+    let mut input = String::new();
+    util::header();
+
+    for (i, region) in regions.iter().enumerate() {
+        println!("{} {:#07x}-{:#07x} {}", format!("({})", i + 1).bright_blue(), region.start, region.end - 1, region.label);
+    }
+
+    println!();
+    util::prompt("Select a region to jump to, or enter to go back: ", &mut input);
+    match input.trim().parse::<usize>() {
+        Ok(idx) if (1..=regions.len()).contains(&idx) => Some(regions[idx - 1].start),
+        _ => None,
+    }
+}
+
+// Hex dump of the whole ROM with the known structures marked
+pub fn view_rom(vbios: &Vbios) {
+    let rom = &vbios.rom;
+    let regions = known_regions(vbios);
+    let mut start = 0;
+    let mut highlight: Option<(usize, usize)> = None;
+    let mut message = String::new();
+
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("ROM is {:#x} bytes, {} known regions", rom.len(), regions.len());
+        println!();
+        print_page(rom, &regions, start, highlight);
+        println!();
+
+        if !message.is_empty() {
+            println!("{}", message);
+            message.clear();
+        }
+
+        println!("{} next page  {} previous page  {} go to offset  {} search  {} regions  {} back",
+            "n".bright_blue(), "p".bright_blue(), "g <hex offset>".bright_blue(),
+            "s <hex bytes or \"text\">".bright_blue(), "l".bright_blue(), "q".bright_blue());
+        util::prompt("> ", &mut input);
+
+        let input = input.trim();
+        let (command, arg) = match input.split_once(' ') {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (input, ""),
+        };
+
+        match command.to_lowercase().as_str() {
+            "q" => break,
+            "" | "n" if start + PAGE_SIZE < rom.len() => start += PAGE_SIZE,
+            "p" => start = start.saturating_sub(PAGE_SIZE),
+            "g" => match usize::from_str_radix(arg.trim_start_matches("0x"), 16) {
+                Ok(offset) if offset < rom.len() => {
+                    start = offset - offset % BYTES_PER_LINE;
+                    highlight = Some((offset, 1));
+                }
+                _ => message = format!("{} offset must be in hex and inside the ROM", "Invalid offset:".red()),
+            },
+            "s" => {
+                let pattern = match parse_pattern(arg) {
+                    Some(pattern) => pattern,
+                    None => {
+                        message = format!("{} use hex bytes (ie. \"s cb bd dc 4e\") or quoted text", "Invalid pattern:".red());
+                        continue;
+                    }
+                };
+
+                // Carry on from the last match, so searching again finds the next one
+                let from = match highlight {
+                    Some((found, _)) => found + 1,
+                    None => start,
+                };
+
+                match find_pattern(rom, &pattern, from) {
+                    Some(found) => {
+                        start = found - found % BYTES_PER_LINE;
+                        highlight = Some((found, pattern.len()));
+                        message = format!("Found at {:#x}", found);
+                    }
+                    None => message = "Not found".yellow().to_string(),
+                }
+            }
+            "l" => {
+                if let Some(offset) = choose_region(&regions) {
+                    start = offset - offset % BYTES_PER_LINE;
+                    highlight = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvidia;
+    use crate::test_support::*;
+
+    #[test]
+    fn finds_known_regions() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .connector(CONN_DVI_I)
+            .bit_entry(b'I', 1, &[0; 8])
+            .build();
+        let vbios = nvidia::parse_rom(&rom).unwrap();
+
+        let regions: Vec<String> = known_regions(&vbios).iter()
+            .map(|region| format!("{:#x}-{:#x} {}", region.start, region.end, region.label))
+            .collect();
+
+        assert_eq!(regions, vec![
+            "0x0-0x1a PCI ROM header",
+            "0x40-0x58 PCIR",
+            "0x60-0x6c BIT header (version 0x0100)",
+            "0x6c-0x72 BIT entries",
+            "0x100-0x117 DCB header",
+            "0x117-0x11f DCB entry 0x0",
+            "0x11f-0x127 DCB entry 0x1",
+            "0x127-0x130 Connector table",
+            "0x130-0x138 BIT 'I' (Init tables) version 1 data",
+        ]);
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let rom = b"..BIT....BIT..";
+        assert_eq!(find_pattern(rom, b"BIT", 0), Some(2));
+        assert_eq!(find_pattern(rom, b"BIT", 3), Some(9));
+        assert_eq!(find_pattern(rom, b"BIT", 10), Some(2));
+        assert_eq!(find_pattern(rom, b"NV", 0), None);
+    }
+
+    #[test]
+    fn parses_search_patterns() {
+        assert_eq!(parse_pattern("cb bd dc 4e"), Some(vec![0xcb, 0xbd, 0xdc, 0x4e]));
+        assert_eq!(parse_pattern("\"BIT\""), Some(b"BIT".to_vec()));
+        assert_eq!(parse_pattern("\"\""), None);
+        assert_eq!(parse_pattern("xyz"), None);
+    }
+}
@@ -0,0 +1,92 @@
+// Don't let a long session eat memory, nobody undoes this far back
+const MAX_HISTORY: usize = 100;
+
+// Undo/redo stacks of whole snapshots, along with a log of everything that happened
+pub struct History<T: Clone + PartialEq> {
+    undo: Vec<(T, String)>,
+    redo: Vec<(T, String)>,
+    log: Vec<String>,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    pub fn new() -> History<T> {
+        History { undo: vec![], redo: vec![], log: vec![] }
+    }
+
+    // Remember `before` if an edit actually changed something
+    pub fn record(&mut self, before: T, after: &T, description: String) {
+        if before == *after {
+            return;
+        }
+
+        self.undo.push((before, description.clone()));
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+
+        self.redo.clear();
+        self.log.push(description);
+    }
+
+    // Returns what was undone, if there was anything to undo
+    pub fn undo(&mut self, current: &mut T) -> Option<String> {
+        let (previous, description) = self.undo.pop()?;
+        let newer = std::mem::replace(current, previous);
+
+        self.log.push(format!("Undo: {}", description));
+        self.redo.push((newer, description.clone()));
+        Some(description)
+    }
+
+    // Returns what was redone, if there was anything to redo
+    pub fn redo(&mut self, current: &mut T) -> Option<String> {
+        let (next, description) = self.redo.pop()?;
+        let older = std::mem::replace(current, next);
+
+        self.log.push(format!("Redo: {}", description));
+        self.undo.push((older, description.clone()));
+        Some(description)
+    }
+
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_and_redoes() {
+        let mut history: History<u32> = History::new();
+        let mut state = 0;
+
+        for value in 1..=3 {
+            let before = state;
+            state = value;
+            history.record(before, &state, format!("{} -> {}", before, value));
+        }
+
+        // Nothing changed, so nothing to remember
+        history.record(state, &state, "nothing".to_owned());
+
+        assert_eq!(history.undo(&mut state).as_deref(), Some("2 -> 3"));
+        assert_eq!(history.undo(&mut state).as_deref(), Some("1 -> 2"));
+        assert_eq!(state, 1);
+
+        assert_eq!(history.redo(&mut state).as_deref(), Some("1 -> 2"));
+        assert_eq!(state, 2);
+
+        assert_eq!(history.undo(&mut state).as_deref(), Some("1 -> 2"));
+        assert_eq!(history.undo(&mut state).as_deref(), Some("0 -> 1"));
+        assert_eq!(state, 0);
+        assert!(history.undo(&mut state).is_none());
+
+        // A new edit drops whatever could still be redone
+        history.record(state, &5, "0 -> 5".to_owned());
+        state = 5;
+        assert!(history.redo(&mut state).is_none());
+        assert_eq!(history.log().len(), 9);
+    }
+}
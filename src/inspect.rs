@@ -0,0 +1,193 @@
+use colored::*;
+use crate::bit;
+use crate::bmp::Bmp;
+use crate::lvds;
+use crate::nvidia::{self, DcbEntry, DcbHeader, Vbios};
+use crate::util;
+
+// Colors cycled through to tell neighbouring bitfields apart
+const FIELD_COLORS: [Color; 4] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Green];
+
+fn field_color(field: usize) -> Color {
+    FIELD_COLORS[field % FIELD_COLORS.len()]
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+fn format_pointer(pointer: Option<usize>) -> String {
+    match pointer {
+        Some(pointer) => format!("{:#x}", pointer),
+        None => "none".to_owned(),
+    }
+}
+
+fn print_bmp(bmp: &Bmp) {
+    println!("{} at {:#x}", "BMP".bright_blue(), bmp.offset);
+    println!("{} {}.{:x} {} {} {} {:#x}",
+        "Version:".green(), bmp.version_major, bmp.version_minor,
+        "BIOS Version:".green(), bmp.bios_version_string(),
+        "Length:".green(), bmp.length,
+    );
+    println!("{} {:#x} {} {} {} {} {} {}",
+        "I2C Ports at".green(), bmp.i2c_offset,
+        "CRT:".green(), bmp.i2c_crt,
+        "TV:".green(), bmp.i2c_tv,
+        "Panel:".green(), bmp.i2c_panel,
+    );
+    println!("{} {} {} {} {} {} {} {}",
+        "Init Scripts:".green(), format_pointer(bmp.init_script_table),
+        "FP Table:".green(), format_pointer(bmp.fp_table),
+        "FP Strap Table:".green(), format_pointer(bmp.fp_xlate_table),
+        "LVDS Manufacturer Table:".green(), format_pointer(bmp.lvds_manufacturer_table),
+    );
+}
+
+fn print_bit(bit: &bit::Bit) {
+    println!("{} at {:#x}", "BIT".bright_blue(), bit.offset);
+    for entry in &bit.entries {
+        println!("{} '{}' ({}) {} {} {} {:#x} {} {:#x}",
+            "Entry".green(), entry.id as char, bit::bit_id_to_string(entry.id),
+            "Version:".green(), entry.version,
+            "Size:".green(), entry.size,
+            "Pointer:".green(), entry.pointer,
+        );
+    }
+}
+
+fn print_header(dcb: &DcbHeader, rom: &[u8]) {
+    println!("{} at {:#x}", "DCB Header".bright_blue(), dcb.offset);
+    println!("{} {}.{} ({:#x}) {} {:#x} {} {} {} {:#x}",
+        "Version:".green(), dcb.version >> 4, dcb.version & 0xf, dcb.version,
+        "Header Size:".green(), dcb.size,
+        "Entries:".green(), dcb.entry_count,
+        "Entry Size:".green(), dcb.entry_size,
+    );
+
+    let flags = match dcb.flags {
+        Some(flags) => format!("{:#04x}", flags),
+        None => "none".to_owned(),
+    };
+    println!("{} {} {} {} {} {} {} {}",
+        "I2C Table:".green(), format_pointer(dcb.i2c_table),
+        "GPIO Table:".green(), format_pointer(dcb.gpio_table),
+        "Connector Table:".green(), format_pointer(dcb.connector_table),
+        "Flags:".green(), flags,
+    );
+
+    let end = (dcb.offset + dcb.size).min(rom.len());
+    println!("{} {}", "Raw:".green(), hex_bytes(&rom[dcb.offset..end]));
+}
+
+// Index into DCB_FIELDS of the field holding all 4 bits of a nibble, if there is one
+fn nibble_field(nibble: u32) -> Option<usize> {
+    nvidia::DCB_FIELDS.iter().position(|(_, (shift, width))| *shift <= nibble * 4 && nibble * 4 + 4 <= shift + width)
+}
+
+// Raw bytes of the entry, with each hex digit colored by the field it belongs to.
+// Digits split between several fields, and bytes past the first 32 bits, are left plain.
+fn print_entry_hex(raw: &[u8]) {
+    let mut out = String::new();
+    for (i, byte) in raw.iter().enumerate() {
+        if i > 0 {
+            out += " ";
+        }
+
+        for (nibble, digit) in [(i * 2 + 1, byte >> 4), (i * 2, byte & 0xf)] {
+            let digit = format!("{:x}", digit);
+            match nibble_field(nibble as u32) {
+                Some(field) if i < 4 => out += &digit.color(field_color(field)).to_string(),
+                _ => out += &digit,
+            }
+        }
+    }
+
+    println!("{} {}", "Raw:".green(), out);
+}
+
+// The first 32 bits split into fields, highest bits first, with the field names lined up above
+fn print_entry_bits(entry: &DcbEntry) {
+    let conn = entry.encode();
+    let mut names: Vec<String> = vec![];
+    let mut bits: Vec<String> = vec![];
+
+    for (i, (name, field)) in nvidia::DCB_FIELDS.iter().enumerate() {
+        let width = (field.1 as usize).max(name.len());
+        let value = format!("{:0width$b}", nvidia::get_bits(conn, *field), width = field.1 as usize);
+
+        names.push(format!("{:<width$}", name, width = width).color(field_color(i)).to_string());
+        bits.push(format!("{:<width$}", value, width = width).color(field_color(i)).to_string());
+    }
+
+    println!("      {}", names.join(" "));
+    println!("{} {}", "Bits:".green(), bits.join(" "));
+}
+
+fn print_entry(entry: &DcbEntry, dcb: &DcbHeader, rom: &[u8]) {
+    println!("{} {:#x} at {:#x}", "DCB Entry".bright_blue(), entry.entry, entry.offset);
+    println!("{} {} ({:#x}) {} {} {} {} {} {} {} {} {} {}",
+        "Type:".green(), nvidia::dcb_type_to_string(entry.entry_type), entry.entry_type,
+        "EdidPort:".green(), entry.edid_port,
+        "Head:".green(), entry.head_bitmask,
+        "Connector:".green(), entry.con,
+        "Bus:".green(), entry.bus,
+        "Loc:".green(), entry.loc,
+    );
+
+    println!("{} {} {} {} {} {} {} {} {} {}",
+        "BDR:".green(), entry.bdr,
+        "BBDR:".green(), entry.bbdr,
+        "Resources:".green(), entry.output_resources,
+        "Virtual:".green(), entry.entry_is_virtual,
+        "Reserved:".green(), entry.reserved,
+    );
+
+    let end = (entry.offset + (dcb.entry_size as usize).max(4)).min(rom.len());
+    print_entry_hex(&rom[entry.offset..end]);
+    print_entry_bits(entry);
+}
+
+// Everything read out of the BMP or BIT and the DCB, down to the raw bytes
+pub fn inspect_dcb(vbios: &Vbios) {
+    util::header();
+
+    if let Some(bmp) = &vbios.bmp {
+        print_bmp(bmp);
+        println!();
+    }
+
+    if let Some(bit) = &vbios.bit {
+        print_bit(bit);
+        println!();
+    }
+
+    if let Some(lvds) = &vbios.lvds {
+        println!("{} at {:#x}", "LVDS Tables".bright_blue(), lvds.offset);
+        for line in lvds::describe_panel(lvds) {
+            println!("{}", line);
+        }
+        println!();
+    }
+
+    print_header(&vbios.dcb, &vbios.rom);
+
+    for entry in &vbios.dcb_entries {
+        println!();
+        print_entry(entry, &vbios.dcb, &vbios.rom);
+    }
+
+    if !vbios.connectors.is_empty() {
+        println!();
+        println!("{} at {}", "Connector Table".bright_blue(), format_pointer(vbios.dcb.connector_table));
+        for conn in &vbios.connectors {
+            println!("{} {:#x} {} {} ({:#x}) {} {:#010x}",
+                "Connector".green(), conn.index,
+                "Type:".green(), nvidia::connector_type_to_string(conn.conn_type), conn.conn_type,
+                "Raw:".green(), conn.raw,
+            );
+        }
+    }
+
+    util::press_any_key();
+}
@@ -0,0 +1,304 @@
+use std::fs;
+use colored::*;
+use crate::gpus::NVIDIA_VENDOR_ID;
+use crate::nvidia::{NVCAP, NVCAP_SIZE};
+use crate::util::{self, NVErrors};
+
+// Reads the text `ioreg -lw0` prints on macOS. Every object is a "+-o Name@address  <class ...>"
+// line, indented by how deep it is in the tree, followed by its properties as "key" = value.
+const NODE_MARKER: &str = "+-o ";
+const PCI_DEVICE_CLASS: &str = "IOPCIDevice";
+// Bridges between PCI devices have no address and don't show up in device paths
+const PCI_BRIDGE_CLASS: &str = "IOPCI2PCIBridge";
+// Base class byte of class-code
+const DISPLAY_CLASS: u8 = 0x03;
+
+pub struct IoregDevice {
+    // ie. GFX0@0
+    pub name: String,
+    // ie. PciRoot(0x0)/Pci(0x1,0x0)/Pci(0x0,0x0)
+    pub pci_path: Option<String>,
+    // ie. _SB.PCI0.PEG0.GFX0
+    pub acpi_path: Option<String>,
+    pub device_id: Option<u16>,
+    pub rom_revision: Option<String>,
+    pub nvcap: Option<[u8; NVCAP_SIZE]>,
+}
+
+struct Node {
+    depth: usize,
+    name: String,
+    class: String,
+    properties: Vec<(String, String)>,
+    // Indexes of the parents, starting at the root
+    parents: Vec<usize>,
+}
+
+impl Node {
+    fn property(&self, key: &str) -> Option<&str> {
+        self.properties.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+}
+
+// "+-o PEG0@1  <class IOPCIDevice, id 0x100000200, registered, ...>"
+fn parse_node(line: &str) -> Option<(usize, String, String)> {
+    let depth = line.find(NODE_MARKER)?;
+    let rest = &line[depth + NODE_MARKER.len()..];
+    let (name, class) = match rest.split_once("<class ") {
+        Some((name, class)) => (name.trim(), class.split([',', '>']).next().unwrap_or("").trim()),
+        None => (rest.trim(), ""),
+    };
+
+    Some((depth, name.to_owned(), class.to_owned()))
+}
+
+// "| |   "device-id" = <e90f0000>"
+fn parse_property(line: &str) -> Option<(String, String)> {
+    let line = line.trim_start_matches([' ', '|']);
+    let rest = line.strip_prefix('"')?;
+    let (key, value) = rest.split_once("\" = ")?;
+    Some((key.to_owned(), value.trim().to_owned()))
+}
+
+fn parse_tree(text: &str) -> Vec<Node> {
+    let mut nodes: Vec<Node> = vec![];
+    // Nodes on the way from the root to the last one seen
+    let mut stack: Vec<usize> = vec![];
+
+    for line in text.lines() {
+        if let Some((depth, name, class)) = parse_node(line) {
+            while stack.last().is_some_and(|&parent| nodes[parent].depth >= depth) {
+                stack.pop();
+            }
+
+            nodes.push(Node { depth, name, class, properties: vec![], parents: stack.clone() });
+            stack.push(nodes.len() - 1);
+        } else if let (Some((key, value)), Some(node)) = (parse_property(line), nodes.last_mut()) {
+            node.properties.push((key, value));
+        }
+    }
+
+    nodes
+}
+
+// Data is printed as <hex>, or <"text"> when it's printable
+fn parse_data(value: &str) -> Option<Vec<u8>> {
+    let inner = value.strip_prefix('<')?.strip_suffix('>')?;
+    match inner.strip_prefix('"').and_then(|text| text.strip_suffix('"')) {
+        Some(text) => Some(text.as_bytes().to_vec()),
+        None => util::parse_hex_bytes(inner),
+    }
+}
+
+// Strings are "text", but some drivers store them as data
+fn parse_string(value: &str) -> Option<String> {
+    let text = match value.strip_prefix('"').and_then(|text| text.strip_suffix('"')) {
+        Some(text) => text.to_owned(),
+        None => String::from_utf8_lossy(&parse_data(value)?).to_string(),
+    };
+
+    Some(text.trim_end_matches('\0').to_owned()).filter(|text| !text.is_empty())
+}
+
+fn data_u32(node: &Node, key: &str) -> Option<u32> {
+    let data = parse_data(node.property(key)?)?;
+    (data.len() >= 4).then(|| util::read_uint_32_le(&data, 0))
+}
+
+// "PEG0@1" -> (1, 0), "P0P2@3,1" -> (3, 1)
+fn pci_address(name: &str) -> Option<(u32, u32)> {
+    let (_, address) = name.split_once('@')?;
+    let (device, function) = address.split_once(',').unwrap_or((address, "0"));
+    Some((u32::from_str_radix(device, 16).ok()?, u32::from_str_radix(function, 16).ok()?))
+}
+
+// Walks up to the PCI root bridge. Its UID isn't in the dump, but nearly every board
+// has a single root bridge with UID 0.
+fn pci_path(nodes: &[Node], device: usize) -> Option<String> {
+    let mut path: Vec<String> = vec![];
+    for &idx in nodes[device].parents.iter().chain([device].iter()).rev() {
+        let node = &nodes[idx];
+        if node.class == PCI_DEVICE_CLASS {
+            let (device, function) = pci_address(&node.name)?;
+            path.push(format!("Pci({:#x},{:#x})", device, function));
+        } else if node.class != PCI_BRIDGE_CLASS {
+            // First thing that isn't a PCI device is the root bridge, ie. PCI0@0
+            pci_address(&node.name)?;
+            path.push("PciRoot(0x0)".to_owned());
+            path.reverse();
+            return Some(path.join("/"));
+        }
+    }
+
+    None
+}
+
+// "IOACPIPlane:/_SB/PCI0@0/PEG0@10000/GFX0@0" -> "_SB.PCI0.PEG0.GFX0"
+fn acpi_path(value: &str) -> Option<String> {
+    let path = parse_string(value)?;
+    let path = path.split_once(":/").map_or(path.as_str(), |(_, path)| path);
+    let parts: Vec<&str> = path.split('/')
+        .map(|part| part.split('@').next().unwrap_or(part))
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    (!parts.is_empty()).then(|| parts.join("."))
+}
+
+fn is_nvidia_display(node: &Node) -> bool {
+    let vendor = data_u32(node, "vendor-id").map(|id| id as u16);
+    let class = data_u32(node, "class-code").map(|class| (class >> 16) as u8);
+    node.class == PCI_DEVICE_CLASS && vendor == Some(NVIDIA_VENDOR_ID)
+        && (class == Some(DISPLAY_CLASS) || node.property("NVCAP").is_some())
+}
+
+pub fn parse_ioreg(text: &str) -> Vec<IoregDevice> {
+    let nodes = parse_tree(text);
+
+    (0..nodes.len()).filter(|&idx| is_nvidia_display(&nodes[idx])).map(|idx| {
+        let node = &nodes[idx];
+        let nvcap = node.property("NVCAP").and_then(parse_data).filter(|bytes| bytes.len() == NVCAP_SIZE).map(|bytes| {
+            let mut nvcap = [0u8; NVCAP_SIZE];
+            nvcap.copy_from_slice(&bytes);
+            nvcap
+        });
+
+        IoregDevice {
+            name: node.name.clone(),
+            pci_path: pci_path(&nodes, idx),
+            acpi_path: node.property("acpi-path").and_then(acpi_path),
+            device_id: data_u32(node, "device-id").map(|id| id as u16),
+            rom_revision: node.property("rom-revision").and_then(parse_string),
+            nvcap,
+        }
+    }).collect()
+}
+
+pub fn read_ioreg(filename: &str) -> Result<Vec<IoregDevice>, NVErrors> {
+    match fs::read(filename) {
+        Ok(bytes) => Ok(parse_ioreg(&String::from_utf8_lossy(&bytes))),
+        Err(e) => {
+            println!("{}", "ioreg dump not found!".red());
+            println!("{}", e);
+            Err(NVErrors::FileNotFound)
+        }
+    }
+}
+
+// Lines describing a device, with its NVCAP decoded
+pub fn describe_device(device: &IoregDevice) -> Vec<String> {
+    let unknown = || "unknown".to_owned();
+    let mut lines = vec![
+        format!("{} {}", "Device ID:".green(), device.device_id.map_or_else(unknown, |id| format!("{:#06x}", id))),
+        format!("{} {}", "ROM revision:".green(), device.rom_revision.clone().unwrap_or_else(unknown)),
+        format!("{} {}", "PCI path:".green(), device.pci_path.clone().unwrap_or_else(unknown)),
+        format!("{} {}", "ACPI path:".green(), device.acpi_path.clone().unwrap_or_else(unknown)),
+    ];
+
+    let bytes = match &device.nvcap {
+        Some(bytes) => bytes,
+        None => {
+            lines.push(format!("{} not set", "NVCAP:".green()));
+            return lines;
+        }
+    };
+
+    let nvcap = NVCAP::from_bytes(bytes);
+    lines.push(format!("{} {}", "NVCAP:".green(), bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()));
+    lines.push(format!("  Version: {}  Mobile: {}  Composite: {}  Script Based Power/Backlight: {}",
+        nvcap.version, nvcap.is_mobile, nvcap.is_composite, nvcap.script_based_power_and_backlight));
+    lines.push(format!("  TV mask: {:#x}  Head 1 mask: {:#x}  Head 2 mask: {:#x}  Field F: {:#x}  EDID Bitness: {:#x}",
+        nvcap.dcb_tv_mask, nvcap.dcb_0_mask, nvcap.dcb_1_mask, nvcap.field_f, nvcap.edid_bitness));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export;
+    use crate::nvidia::MergeStrategy;
+    use crate::session::Session;
+
+    const IOREG: &str = r#"+-o Root  <class IORegistryEntry, id 0x100000100, retain 20>
+  +-o MacBookPro10,1  <class IOPlatformExpertDevice, id 0x10000020f, registered, matched, active, busy 0 (120000 ms), retain 36>
+    | {
+    |   "model" = <"MacBookPro10,1">
+    | }
+    |
+    +-o AppleACPIPlatformExpert  <class AppleACPIPlatformExpert, id 0x100000210, registered, matched, active, busy 0 (100 ms), retain 40>
+      +-o PCI0@0  <class AppleACPIPCI, id 0x100000250, registered, matched, active, busy 0 (80 ms), retain 30>
+      | +-o PEG0@1  <class IOPCIDevice, id 0x100000260, registered, matched, active, busy 0 (60 ms), retain 14>
+      | | {
+      | |   "vendor-id" = <86800000>
+      | |   "class-code" = <00040600>
+      | | }
+      | |
+      | | +-o IOPP  <class IOPCI2PCIBridge, id 0x100000300, registered, matched, active, busy 0 (50 ms), retain 10>
+      | |   +-o GFX0@0  <class IOPCIDevice, id 0x100000310, registered, matched, active, busy 0 (40 ms), retain 25>
+      | |   | {
+      | |   |   "vendor-id" = <de100000>
+      | |   |   "device-id" = <e90f0000>
+      | |   |   "class-code" = <00000300>
+      | |   |   "acpi-path" = "IOACPIPlane:/_SB/PCI0@0/PEG0@10000/GFX0@0"
+      | |   |   "rom-revision" = <"80.07.35.00.0F">
+      | |   |   "NVCAP" = <0501000000000100060000000000000f00000000>
+      | |   | }
+      | |   |
+      | |   +-o HDAU@0,1  <class IOPCIDevice, id 0x100000320, registered, matched, active, busy 0 (30 ms), retain 12>
+      | |     {
+      | |       "vendor-id" = <de100000>
+      | |       "class-code" = <00030400>
+      | |     }
+      | |
+      | +-o IGPU@2  <class IOPCIDevice, id 0x100000270, registered, matched, active, busy 0 (20 ms), retain 18>
+      |   {
+      |     "vendor-id" = <86800000>
+      |     "class-code" = <00000300>
+      |   }
+"#;
+
+    #[test]
+    fn finds_nvidia_gpus() {
+        let devices = parse_ioreg(IOREG);
+        assert_eq!(devices.len(), 1);
+
+        let gpu = &devices[0];
+        assert_eq!(gpu.name, "GFX0@0");
+        assert_eq!(gpu.device_id, Some(0x0fe9));
+        assert_eq!(gpu.rom_revision.as_deref(), Some("80.07.35.00.0F"));
+        assert_eq!(gpu.pci_path.as_deref(), Some("PciRoot(0x0)/Pci(0x1,0x0)/Pci(0x0,0x0)"));
+        assert_eq!(gpu.acpi_path.as_deref(), Some("_SB.PCI0.PEG0.GFX0"));
+
+        let nvcap = NVCAP::from_bytes(&gpu.nvcap.unwrap());
+        assert_eq!((nvcap.version, nvcap.is_mobile, nvcap.field_f), (5, true, 0x0f));
+    }
+
+    #[test]
+    fn exports_the_imported_device_path() {
+        let devices = parse_ioreg(&IOREG.replace("PEG0@1 ", "PEG0@3 "));
+        let mut session = Session {
+            rom_path: "board.rom".to_owned(),
+            rom_crc32: 0,
+            merge: MergeStrategy::Bus,
+            head_tv: vec![],
+            head_0: vec![0],
+            head_1: vec![1],
+            nvcap: NVCAP::default(),
+            device_path: None,
+        };
+
+        assert_eq!(session.import_device_path(&devices[0]), Some("PciRoot(0x0)/Pci(0x3,0x0)/Pci(0x0,0x0)"));
+        let snippet = export::opencore_snippet(session.device_path.as_deref().unwrap(), &session.nvcap.to_bytes());
+        assert!(snippet.contains("<key>PciRoot(0x0)/Pci(0x3,0x0)/Pci(0x0,0x0)</key>"));
+    }
+
+    #[test]
+    fn parses_property_values() {
+        assert_eq!(parse_data("<de100000>"), Some(vec![0xde, 0x10, 0, 0]));
+        assert_eq!(parse_data("<\"NV\">"), Some(b"NV".to_vec()));
+        assert_eq!(parse_string("<\"80.07.35.00.0F\\0\">".replace("\\0", "\0").as_str()), Some("80.07.35.00.0F".to_owned()));
+        assert_eq!(pci_address("P0P2@3,1"), Some((3, 1)));
+        assert_eq!(pci_address("IOPP"), None);
+        assert!(parse_ioreg("not an ioreg dump").is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+use crate::bit::Bit;
+use crate::bmp::Bmp;
+use crate::util;
+
+// Laptop panel tables, following nouveau's legacy LVDS code (parse_lvds_manufacturer_table,
+// parse_fp_mode_table and run_lvds_table). Which strap a panel uses is read from the GPU at
+// boot, so every strap the ROM has an entry for is listed.
+
+// LVDS manufacturer table, version 0x30 is NV4x and 0x40 is G80
+const LVDS_VERSIONS: [u8; 2] = [0x30, 0x40];
+const LVDS_HEADER_SIZE_OFFSET: usize   = 0x1;
+const LVDS_RECORD_SIZE_OFFSET: usize   = 0x2;
+const LVDS_DUAL_LINK_CLOCK_OFFSET: usize = 0x5;
+// Panel power/backlight scripts for link A and B, on then off
+const LVDS_POWER_ON_SCRIPTS_OFFSET: usize  = 0x7;
+const LVDS_POWER_OFF_SCRIPTS_OFFSET: usize = 0xb;
+const LVDS_SCRIPTS_END: usize              = 0xf;
+
+// Bits in the first byte of each 0x30/0x40 manufacturer record. Pre-NV40 0x0a records
+// keep these at 0x4 and 0x10, but those tables aren't parsed here.
+const LVDS_FLAG_DUAL_LINK: u8 = 0x1;
+const LVDS_FLAG_24_BIT: u8    = 0x2;
+
+// Straps are 4 bits, 0xf means the panel is read over DDC
+const STRAP_COUNT: usize = 16;
+pub const PANEL_FROM_EDID: u8 = 0xf;
+
+// Flat panel mode table
+const FP_TABLE_HEADER_SIZE_OFFSET: usize = 0x1;
+const FP_TABLE_RECORD_SIZE_OFFSET: usize = 0x2;
+const FP_TABLE_COUNT_OFFSET: usize       = 0x3;
+// Where the pixel clock is in each record, the other fields are relative to it
+const FP_MODE_CLOCK: usize   = 0;
+const FP_MODE_HACTIVE: usize = 4;
+const FP_MODE_HTOTAL: usize  = 14;
+const FP_MODE_VACTIVE: usize = 18;
+const FP_MODE_VTOTAL: usize  = 28;
+const FP_MODE_SIZE: usize    = 31;
+
+// BIT 'L' points at the manufacturer table, BIT 'D' has the mode table pointer at +2
+const BIT_LVDS_ID: u8 = b'L';
+const BIT_DISPLAY_ID: u8 = b'D';
+const BIT_DISPLAY_FP_TABLE_OFFSET: usize = 2;
+
+pub struct PanelMode {
+    // kHz
+    pub clock: u32,
+    pub width: u16,
+    pub height: u16,
+    pub htotal: u16,
+    pub vtotal: u16,
+}
+
+pub struct PanelStrap {
+    pub strap: u8,
+    pub flags: u8,
+    // Entry in the mode table, or PANEL_FROM_EDID
+    pub panel_index: u8,
+    pub mode: Option<PanelMode>,
+}
+
+impl PanelStrap {
+    pub fn dual_link(&self) -> bool {
+        self.flags & LVDS_FLAG_DUAL_LINK != 0
+    }
+
+    pub fn bits_per_color(&self) -> u8 {
+        if self.flags & LVDS_FLAG_24_BIT != 0 { 8 } else { 6 }
+    }
+}
+
+pub struct LvdsTables {
+    pub offset: usize,
+    pub version: u8,
+    pub header_size: usize,
+    pub record_size: usize,
+    // kHz, panels read over DDC use dual link above this
+    pub dual_link_clock: u32,
+    // Non-zero script pointers for panel power and backlight
+    pub power_on_scripts: Vec<usize>,
+    pub power_off_scripts: Vec<usize>,
+    pub fp_table: Option<usize>,
+    pub straps: Vec<PanelStrap>,
+}
+
+impl LvdsTables {
+    pub fn has_power_scripts(&self) -> bool {
+        !self.power_on_scripts.is_empty() || !self.power_off_scripts.is_empty()
+    }
+}
+
+fn read_pointer(rom: &[u8], offset: usize) -> Option<usize> {
+    if offset + 2 > rom.len() {
+        return None;
+    }
+
+    match util::read_uint_16_le(rom, offset) {
+        0 => None,
+        pointer => Some(pointer as usize),
+    }
+}
+
+fn read_scripts(rom: &[u8], offset: usize) -> Vec<usize> {
+    (0..2).filter_map(|link| read_pointer(rom, offset + link * 2)).collect()
+}
+
+// Table pointers come from the BMP on old ROMs and the BIT on newer ones
+fn table_pointers(rom: &[u8], bmp: Option<&Bmp>, bit: Option<&Bit>) -> (Option<usize>, Option<usize>) {
+    if let Some(bmp) = bmp {
+        return (bmp.lvds_manufacturer_table, bmp.fp_table);
+    }
+
+    let bit_pointer = |id: u8, field: usize| bit
+        .and_then(|bit| bit.entries.iter().find(|entry| entry.id == id))
+        .filter(|entry| entry.size >= field + 2)
+        .and_then(|entry| read_pointer(rom, entry.pointer + field));
+
+    (bit_pointer(BIT_LVDS_ID, 0), bit_pointer(BIT_DISPLAY_ID, BIT_DISPLAY_FP_TABLE_OFFSET))
+}
+
+// Start of the mode `index` in the flat panel table, lined up so the clock is at FP_MODE_CLOCK
+fn fp_mode_offset(rom: &[u8], table: usize, index: u8) -> Option<usize> {
+    let version = *rom.get(table)?;
+    let (header_size, record_size, count, clock) = match version {
+        0x05 => (0, 42, STRAP_COUNT, 6),
+        0x10 => (0, 44, STRAP_COUNT, 7),
+        0x20 => (
+            *rom.get(table + FP_TABLE_HEADER_SIZE_OFFSET)? as usize,
+            *rom.get(table + FP_TABLE_RECORD_SIZE_OFFSET)? as usize,
+            *rom.get(table + FP_TABLE_COUNT_OFFSET)? as usize,
+            0,
+        ),
+        _ => return None,
+    };
+
+    if index as usize >= count {
+        return None;
+    }
+
+    Some(table + header_size + record_size * index as usize + clock)
+}
+
+fn parse_fp_mode(rom: &[u8], table: usize, index: u8) -> Option<PanelMode> {
+    let mode = fp_mode_offset(rom, table, index)?;
+    if mode + FP_MODE_SIZE > rom.len() {
+        return None;
+    }
+
+    // Everything but the clock is stored minus one
+    let field = |offset: usize| util::read_uint_16_le(rom, mode + offset).wrapping_add(1);
+    Some(PanelMode {
+        clock: util::read_uint_16_le(rom, mode + FP_MODE_CLOCK) as u32 * 10,
+        width: field(FP_MODE_HACTIVE),
+        height: field(FP_MODE_VACTIVE),
+        htotal: field(FP_MODE_HTOTAL),
+        vtotal: field(FP_MODE_VTOTAL),
+    })
+}
+
+pub fn parse_lvds(rom: &[u8], bmp: Option<&Bmp>, bit: Option<&Bit>) -> Option<LvdsTables> {
+    let (offset, fp_table) = table_pointers(rom, bmp, bit);
+    let offset = offset?;
+    if offset + LVDS_SCRIPTS_END > rom.len() || !LVDS_VERSIONS.contains(&rom[offset]) {
+        return None;
+    }
+
+    let header_size = rom[offset + LVDS_HEADER_SIZE_OFFSET] as usize;
+    let record_size = rom[offset + LVDS_RECORD_SIZE_OFFSET] as usize;
+    // The strap to mode table index byte is right after each record's flags
+    if header_size < LVDS_DUAL_LINK_CLOCK_OFFSET + 2 || record_size < 2 {
+        return None;
+    }
+
+    let mut straps: Vec<PanelStrap> = vec![];
+    for strap in 0..STRAP_COUNT {
+        let record = offset + header_size + strap * record_size;
+        if record + 2 > rom.len() {
+            break;
+        }
+
+        let panel_index = rom[record + 1];
+        let mode = match (fp_table, panel_index) {
+            (Some(table), index) if index != PANEL_FROM_EDID => parse_fp_mode(rom, table, index),
+            _ => None,
+        };
+
+        straps.push(PanelStrap { strap: strap as u8, flags: rom[record], panel_index, mode });
+    }
+
+    // Older headers end before the script pointers
+    let has_scripts = header_size >= LVDS_SCRIPTS_END;
+    Some(LvdsTables {
+        offset,
+        version: rom[offset],
+        header_size,
+        record_size,
+        dual_link_clock: util::read_uint_16_le(rom, offset + LVDS_DUAL_LINK_CLOCK_OFFSET) as u32 * 10,
+        power_on_scripts: if has_scripts { read_scripts(rom, offset + LVDS_POWER_ON_SCRIPTS_OFFSET) } else { vec![] },
+        power_off_scripts: if has_scripts { read_scripts(rom, offset + LVDS_POWER_OFF_SCRIPTS_OFFSET) } else { vec![] },
+        fp_table,
+        straps,
+    })
+}
+
+fn describe_strap(lvds: &LvdsTables, strap: &PanelStrap) -> String {
+    let link = if strap.dual_link() { "dual link" } else { "single link" };
+    if strap.panel_index == PANEL_FROM_EDID {
+        return format!("Strap {:x}: panel from EDID, dual link above {:.1} MHz, {} bpc",
+            strap.strap, lvds.dual_link_clock as f32 / 1000.0, strap.bits_per_color());
+    }
+
+    match &strap.mode {
+        Some(mode) => format!("Strap {:x}: {}x{} ({}x{} total) at {:.2} MHz, {}, {} bpc", strap.strap,
+            mode.width, mode.height, mode.htotal, mode.vtotal, mode.clock as f32 / 1000.0, link, strap.bits_per_color()),
+        None => format!("Strap {:x}: panel {:#x} (no mode found), {}, {} bpc", strap.strap, strap.panel_index,
+            link, strap.bits_per_color()),
+    }
+}
+
+fn format_scripts(scripts: &[usize]) -> String {
+    scripts.iter().map(|script| format!("{:#x}", script)).collect::<Vec<String>>().join(", ")
+}
+
+// Lines describing the panel tables, shown with each LVDS display
+pub fn describe_panel(lvds: &LvdsTables) -> Vec<String> {
+    let mut lines = vec![format!("LVDS table version {:#x} at {:#x}, mode table at {}", lvds.version, lvds.offset,
+        lvds.fp_table.map_or("none".to_owned(), |table| format!("{:#x}", table)))];
+
+    lines.extend(lvds.straps.iter().map(|strap| describe_strap(lvds, strap)));
+
+    if lvds.has_power_scripts() {
+        lines.push(format!("Panel power/backlight: VBIOS scripts (on {}, off {}), see Script Based Power/Backlight",
+            format_scripts(&lvds.power_on_scripts), format_scripts(&lvds.power_off_scripts)));
+    } else {
+        lines.push("Panel power/backlight: no scripts in the LVDS table, left to the driver".to_owned());
+    }
+
+    let bpc: Vec<u8> = lvds.straps.iter().map(|strap| strap.bits_per_color()).collect();
+    if bpc.contains(&6) {
+        lines.push("Some straps are 6 bpc (18 bit) panels, which matters for EDID Bitness".to_owned());
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit;
+    use crate::bmp::{self, BMP_FP_TABLE_OFFSET, BMP_LVDS_MANUFACTURER_OFFSET};
+    use crate::test_support::*;
+
+    // G80 table: strap 0 is a single link 6 bpc 1280x800 panel, strap 1 a dual link 8 bpc
+    // 1920x1200 panel and the rest are 8 bpc panels read over DDC
+    fn lvds_table() -> Vec<u8> {
+        let mut table = vec![
+            0x40, 0x0f, 0x02,    // version, header size, record size
+            0x00, 0x00,
+            0xc0, 0x2b,          // dual link above 112 MHz, in 10 kHz units
+            0x09, 0x00, 0x00, 0x00, // power on scripts for link A and B
+            0x0a, 0x00, 0x00, 0x00, // power off scripts for link A and B
+        ];
+
+        // Flags (bit 0 dual link, bit 1 24 bit) and the mode table index of each strap
+        table.extend_from_slice(&[0x00, 0x00, 0x03, 0x01]);
+        for _ in 2..STRAP_COUNT {
+            table.extend_from_slice(&[0x02, PANEL_FROM_EDID]);
+        }
+        table
+    }
+
+    fn fp_mode(clock: u16, width: u16, height: u16, htotal: u16, vtotal: u16) -> Vec<u8> {
+        let mut mode = vec![0u8; 0x20];
+        for (offset, value) in [(0, clock), (4, width - 1), (14, htotal - 1), (18, height - 1), (28, vtotal - 1)] {
+            mode[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        mode
+    }
+
+    fn fp_table() -> Vec<u8> {
+        let mut table = vec![0x20, 0x5, 0x20, 0x2, 0x0];
+        table.extend(fp_mode(7100, 1280, 800, 1440, 823));
+        table.extend(fp_mode(15400, 1920, 1200, 2080, 1235));
+        table
+    }
+
+    #[test]
+    fn parses_panels_from_bmp() {
+        let rom = RomBuilder::new()
+            .bmp(5, 0x25)
+            .bmp_pointer(BMP_LVDS_MANUFACTURER_OFFSET, &lvds_table())
+            .bmp_pointer(BMP_FP_TABLE_OFFSET, &fp_table())
+            .build();
+        let lvds = parse_lvds(&rom, bmp::parse_bmp(&rom).as_ref(), None).unwrap();
+
+        let lines = describe_panel(&lvds);
+        assert_eq!(lines.len(), 1 + STRAP_COUNT + 2);
+        assert_eq!(lines[1..4], [
+            "Strap 0: 1280x800 (1440x823 total) at 71.00 MHz, single link, 6 bpc",
+            "Strap 1: 1920x1200 (2080x1235 total) at 154.00 MHz, dual link, 8 bpc",
+            "Strap 2: panel from EDID, dual link above 112.0 MHz, 8 bpc",
+        ]);
+        assert_eq!(lines[STRAP_COUNT + 1..], [
+            "Panel power/backlight: VBIOS scripts (on 0x9, off 0xa), see Script Based Power/Backlight",
+            "Some straps are 6 bpc (18 bit) panels, which matters for EDID Bitness",
+        ]);
+    }
+
+    #[test]
+    fn parses_panels_from_bit() {
+        let builder = RomBuilder::new();
+        let lvds_offset = builder.data_offset() + 2;
+        let mut lvds_data = (lvds_offset as u16).to_le_bytes().to_vec();
+        lvds_data.extend(lvds_table());
+
+        let rom = builder.bit_entry(b'L', 1, &lvds_data).build();
+        let lvds = parse_lvds(&rom, None, bit::parse_bit(&rom).as_ref()).unwrap();
+
+        assert_eq!(lvds.offset, lvds_offset);
+        assert_eq!(lvds.straps.len(), STRAP_COUNT);
+        assert!(lvds.fp_table.is_none());
+        assert!(lvds.straps[0].mode.is_none());
+        assert!(lvds.has_power_scripts());
+    }
+
+    #[test]
+    fn older_headers_have_no_scripts() {
+        let mut table = lvds_table();
+        table[1] = 0x7;
+        table.drain(0x7..0xf);
+        let rom = RomBuilder::new().bmp(5, 0x25).bmp_pointer(BMP_LVDS_MANUFACTURER_OFFSET, &table).build();
+        let lvds = parse_lvds(&rom, bmp::parse_bmp(&rom).as_ref(), None).unwrap();
+
+        assert!(!lvds.has_power_scripts());
+        assert_eq!(lvds.straps[1].bits_per_color(), 8);
+    }
+
+    #[test]
+    fn rejects_unknown_tables() {
+        let mut table = lvds_table();
+        table[0] = 0x0a;
+        let rom = RomBuilder::new().bmp(5, 0x25).bmp_pointer(BMP_LVDS_MANUFACTURER_OFFSET, &table).build();
+        assert!(parse_lvds(&rom, bmp::parse_bmp(&rom).as_ref(), None).is_none());
+        assert!(parse_lvds(&rom, None, None).is_none());
+    }
+}
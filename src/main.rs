@@ -1,16 +1,36 @@
+mod bit;
+mod bmp;
+mod diff;
+mod edid;
+mod export;
+mod gpus;
+mod heads;
+mod hexview;
+mod history;
+mod inspect;
+mod ioreg;
+mod lvds;
 mod nvidia;
+mod patcher;
+#[cfg(test)]
+mod regression;
+mod script;
+mod session;
+#[cfg(test)]
+mod test_support;
+mod tui;
 mod util;
+mod validate;
 use colored::*;
+use std::env;
 use std::process::exit;
 use std::{io};
 use std::io::prelude::*;
 
-use crate::nvidia::{NVCAP_VERSION_MODERN};
-
 fn main() {
-    let dcb_entries_opt: Vec<nvidia::DcbEntry>;
-    let display_entries_opt: Vec<util::Display>;
+    let mut vbios: nvidia::Vbios;
     let filename;
+    let mut saved: Option<session::Session>;
 
     ctrlc::set_handler(move || {
         util::clear_console();
@@ -21,12 +41,18 @@ fn main() {
     #[cfg(target_os = "windows")]
     control::set_virtual_terminal(true).unwrap();
 
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 4 && args[1] == "diff" {
+        diff_mode(&args[2], &args[3]);
+        return;
+    }
+
     let res = choose_rom();
     match res {
         Ok(tuple) => {
             filename = tuple.0;
-            dcb_entries_opt = tuple.1;
-            display_entries_opt = tuple.2;
+            vbios = tuple.1;
+            saved = tuple.2;
         },
         Err(_) => {
             util::goodbye();
@@ -34,27 +60,48 @@ fn main() {
         }
     }
 
+    let gpu = vbios.pci.as_ref().and_then(|pci| gpus::lookup(pci.vendor_id, pci.device_id));
+
     loop {
         let mut input = String::new();
         let mut opt: u32 = 0;
         util::header();
 
-        println!("{} Show DCB Entries", "(1)".cyan());
+        println!("{} Inspect DCB", "(1)".cyan());
         println!("{} Calculate NVCAP", "(2)".cyan());
-        println!("");
+        println!("{} Compare with another VBIOS", "(3)".cyan());
+        println!("{} Patch DCB Entries", "(4)".cyan());
+        println!("{} Display merging: {}", "(5)".cyan(), vbios.merge.name());
+        println!("{} View ROM hex", "(6)".cyan());
+        println!("{} Disassemble init scripts", "(7)".cyan());
+        println!("{} Attach EDID to a display", "(8)".cyan());
+        println!("{} Import ioreg dump", "(9)".cyan());
+        println!();
         println!("Current ROM file: {}", filename.green());
+        print_gpu(&vbios, gpu);
 
         util::prompt("Type in the number to select your option, or \"q\"/\"quit\" to quit: ", &mut input);
         input = input.trim().to_owned();
-        match input.parse::<u32>() {
-            Ok(val) => { opt = val; }
-            Err(_) => {}
-        }
+        if let Ok(val) = input.parse::<u32>() { opt = val; }
 
         if opt == 1 {
-            dump_dcb_entries(&dcb_entries_opt);
+            inspect::inspect_dcb(&vbios);
         } else if opt == 2 {
-            draw_nvcap(&display_entries_opt);
+            draw_nvcap(&vbios, gpu, &filename, &mut saved);
+        } else if opt == 3 {
+            compare_rom(&vbios, &filename);
+        } else if opt == 4 {
+            patcher::patch_dcb(&vbios, &filename);
+        } else if opt == 5 {
+            choose_merge_strategy(&mut vbios, &mut saved);
+        } else if opt == 6 {
+            hexview::view_rom(&vbios);
+        } else if opt == 7 {
+            script::view_scripts(&vbios);
+        } else if opt == 8 {
+            attach_edid(&mut vbios);
+        } else if opt == 9 {
+            import_ioreg(&vbios, gpu, &filename, &mut saved);
         } else if input.starts_with("q") {
             break;
         }
@@ -63,12 +110,12 @@ fn main() {
     util::goodbye();
 }
 
-fn choose_rom () -> Result<(String, Vec<nvidia::DcbEntry>, Vec<util::Display>), util::NVErrors> {
+fn choose_rom () -> Result<(String, nvidia::Vbios, Option<session::Session>), util::NVErrors> {
     loop {
         let mut filename = String::new();
         util::header();
 
-        println!("Enter in the location of your VBIOS (or q/quit to exit to menu)\n");
+        println!("Enter in the location of your VBIOS or a saved session (or q/quit to exit to menu)\n");
         println!("{} Shift + Right click your VBIOS and click \"Copy Path\"", "Windows Tip: ".cyan());
         println!("{} Drag and drop your VBIOS into this prompt", "Linooox/macOS: ".cyan());
         print!("Location of VBIOS: ");
@@ -92,6 +139,16 @@ fn choose_rom () -> Result<(String, Vec<nvidia::DcbEntry>, Vec<util::Display>),
             return Err(util::NVErrors::FileNotFound);
         }
 
+        if session::is_session_file(&filename) {
+            match load_session(&filename) {
+                Ok(res) => return Ok(res),
+                Err(_) => {
+                    util::press_any_key();
+                    continue;
+                }
+            }
+        }
+
         let read_result = nvidia::read_rom(&filename);
         match read_result {
             Err(_) => {
@@ -100,39 +157,295 @@ fn choose_rom () -> Result<(String, Vec<nvidia::DcbEntry>, Vec<util::Display>),
             }
             Ok(res) => {
                 util::press_any_key();
-                return Ok((filename, res.0, res.1));
+                return Ok((filename, res, None));
             }
         }
     }
 }
 
-fn dump_dcb_entries(dcb_entries: &Vec<nvidia::DcbEntry>) {
+fn load_session(path: &str) -> Result<(String, nvidia::Vbios, Option<session::Session>), util::NVErrors> {
+    let mut state = session::load(path)?;
+    println!("Loaded session for {}", state.rom_path.green());
+
+    let mut vbios = nvidia::read_rom(&state.rom_path)?;
+    vbios.set_merge_strategy(state.merge);
+    let in_range = |head: &Vec<usize>| head.iter().all(|&idx| idx < vbios.displays.len());
+    let heads_valid = in_range(&state.head_tv) && in_range(&state.head_0) && in_range(&state.head_1);
+
+    if vbios.crc32 != state.rom_crc32 || !heads_valid {
+        println!();
+        if vbios.crc32 != state.rom_crc32 {
+            println!("{} The ROM has changed since this session was saved ({:#010x}, expected {:#010x})",
+                "Warning:".yellow(), vbios.crc32, state.rom_crc32);
+        }
+
+        if !heads_valid {
+            println!("{} The session assigns displays that this ROM doesn't have (it has {})",
+                "Warning:".yellow(), vbios.displays.len());
+        }
+
+        let mut input = String::new();
+        if heads_valid {
+            util::prompt("Use the saved head assignments anyway? (y/N): ", &mut input);
+        }
+
+        if !input.trim().to_lowercase().starts_with('y') {
+            println!("Starting over with this ROM instead");
+            util::press_any_key();
+            return Ok((state.rom_path, vbios, None));
+        }
+
+        state.rom_crc32 = vbios.crc32;
+    } else {
+        util::press_any_key();
+    }
+
+    Ok((state.rom_path.clone(), vbios, Some(state)))
+}
+
+fn print_gpu(vbios: &nvidia::Vbios, gpu: Option<&gpus::GpuInfo>) {
+    let pci = match &vbios.pci {
+        Some(pci) => pci,
+        None => {
+            println!("GPU: {}", "Unknown (no PCI header in ROM)".yellow());
+            return;
+        }
+    };
+
+    match gpu {
+        Some(gpu) => {
+            println!("GPU: {} [{:04x}:{:04x}] - {}, {}", gpu.name.green(),
+                pci.vendor_id, pci.device_id, gpu.arch, gpu.class());
+            if !gpu.native {
+                println!("{} macOS has no driver for this GPU, NVCAP will not help here",
+                    "Warning:".red());
+            }
+        }
+        None => {
+            println!("GPU: {} [{:04x}:{:04x}]", "Unknown".yellow(), pci.vendor_id, pci.device_id);
+            if pci.vendor_id != gpus::NVIDIA_VENDOR_ID {
+                println!("{} This is not an Nvidia VBIOS", "Warning:".red());
+            }
+        }
+    }
+}
+
+fn suggested_nvcap(vbios: &nvidia::Vbios, filename: &str) -> [u8; nvidia::NVCAP_SIZE] {
+    let gpu = vbios.pci.as_ref().and_then(|pci| gpus::lookup(pci.vendor_id, pci.device_id));
+    let mut state = new_session(vbios, filename, &gpus::suggest_version(gpu), &gpus::suggest_field_f(gpu));
+
+    nvidia::update_head_masks(&mut state.nvcap, &vbios.displays, &state.head_tv, &state.head_0, &state.head_1);
+    state.nvcap.to_bytes()
+}
+
+fn compare_rom(vbios: &nvidia::Vbios, filename: &str) {
+    let mut other_filename = String::new();
     util::header();
 
-    for i in 0..dcb_entries.len() {
-        let dcb_entry = &dcb_entries[i];
-        println!("{} {:#x}", "DCB Entry".bright_blue(), i);
-        println!("{} {} ({:#x}) {} {} {} {} {} {} {} {} {} {}",
-            "Type:".green(), nvidia::dcb_type_to_string(dcb_entry.entry_type), dcb_entry.entry_type,
-            "EdidPort:".green(), dcb_entry.edid_port,
-            "Head:".green(), dcb_entry.head_bitmask,
-            "Connector:".green(), dcb_entry.con,
-            "Bus:".green(), dcb_entry.bus,
-            "Loc:".green(), dcb_entry.loc,
-        );
+    util::prompt("Location of the VBIOS to compare against: ", &mut other_filename);
+    let other_filename = other_filename.trim().replace("\"", "");
 
-        println!("{} {} {} {} {} {} {} {}",
-            "BDR:".green(), dcb_entry.bdr,
-            "BBDR:".green(), dcb_entry.bbdr,
-            "Resources:".green(), dcb_entry.output_resources,
-            "Virtual:".green(), dcb_entry.entry_is_virtual,
-        );
+    let other = match nvidia::read_rom(&other_filename) {
+        Ok(mut other) => {
+            other.set_merge_strategy(vbios.merge);
+            other
+        }
+        Err(_) => {
+            util::press_any_key();
+            return;
+        }
+    };
+
+    println!();
+    diff::print_diff(filename, vbios, &suggested_nvcap(vbios, filename),
+                     &other_filename, &other, &suggested_nvcap(&other, &other_filename));
+    util::press_any_key();
+}
+
+// `nvcap_calculator diff <old rom> <new rom>` compares two ROMs without going through the menus
+fn diff_mode(old_filename: &str, new_filename: &str) {
+    let (old, new) = match (nvidia::read_rom(old_filename), nvidia::read_rom(new_filename)) {
+        (Ok(old), Ok(new)) => (old, new),
+        _ => exit(1),
+    };
+
+    println!();
+    diff::print_diff(old_filename, &old, &suggested_nvcap(&old, old_filename),
+                     new_filename, &new, &suggested_nvcap(&new, new_filename));
+}
+
+// Check a monitor against the output it's plugged into, and use it for suggestions
+fn attach_edid(vbios: &mut nvidia::Vbios) {
+    let mut input = String::new();
+    util::header();
+
+    println!("Choose the display the monitor is connected to:");
+    for (i, display) in vbios.displays.iter().enumerate() {
+        let edid = display.edid.as_ref().map_or("no EDID".to_owned(), |edid| edid.summary());
+        println!("{} {:?} - {}", format!("({})", i + 1).bright_blue(), display.disp_type, edid);
+    }
+
+    println!("{} Return to previous menu", "(q)".bright_blue());
+    println!();
+    util::prompt("Select a display: ", &mut input);
+    let disp_idx = match input.trim().parse::<usize>() {
+        Ok(idx) if (1..=vbios.displays.len()).contains(&idx) => idx - 1,
+        _ => return,
+    };
+
+    println!();
+    println!("{} /sys/class/drm/card0-*/edid, or the IODisplayEDID line from ioreg saved to a file",
+        "Tip:".cyan());
+    input.clear();
+    util::prompt("Location of the EDID (empty to remove the current one): ", &mut input);
+    let filename = input.trim().replace("\"", "");
+
+    let display = &mut vbios.displays[disp_idx];
+    if filename.is_empty() {
+        display.edid = None;
+        return;
+    }
+
+    if let Ok(edid) = edid::read_edid(&filename) {
+        println!("{} {}", "EDID:".green(), edid.summary());
+        if !edid.digital && display.disp_type.is_digital_only() {
+            println!("{} {:?} is digital-only, an analog monitor won't work on it", "Warning:".yellow(), display.disp_type);
+        }
+        display.edid = Some(edid);
+    }
+
+    util::press_any_key();
+}
+
+// Read what macOS currently uses from `ioreg -lw0` output saved to a file
+fn import_ioreg(vbios: &nvidia::Vbios, gpu: Option<&gpus::GpuInfo>, filename: &str,
+                saved: &mut Option<session::Session>) {
+    let mut input = String::new();
+    util::header();
+
+    println!("{} ioreg -lw0 > ioreg.txt", "Tip:".cyan());
+    util::prompt("Location of the ioreg dump: ", &mut input);
+    let path = input.trim().replace("\"", "");
+    if path.is_empty() {
+        return;
+    }
+
+    let devices = match ioreg::read_ioreg(&path) {
+        Ok(devices) => devices,
+        Err(_) => {
+            util::press_any_key();
+            return;
+        }
+    };
+
+    if devices.is_empty() {
+        println!("{}", "No NVIDIA GPUs found in the ioreg dump".red());
+        util::press_any_key();
+        return;
+    }
+
+    println!();
+    for (i, device) in devices.iter().enumerate() {
+        println!("{} {}", format!("({})", i + 1).bright_blue(), device.name);
+        for line in ioreg::describe_device(device) {
+            println!("    {}", line);
+        }
+        println!();
+    }
+
+    input.clear();
+    util::prompt("Select a GPU (q to cancel): ", &mut input);
+    let device = match input.trim().parse::<usize>() {
+        Ok(idx) if (1..=devices.len()).contains(&idx) => &devices[idx - 1],
+        _ => return,
+    };
+
+    let rom_device_id = vbios.pci.as_ref().map(|pci| pci.device_id);
+    if let (Some(rom_id), Some(id)) = (rom_device_id, device.device_id) {
+        if rom_id != id {
+            println!("{} This GPU is {:#06x} but the ROM is for {:#06x}", "Warning:".yellow(), id, rom_id);
+        }
+    }
+
+    let state = saved.get_or_insert_with(|| {
+        new_session(vbios, filename, &gpus::suggest_version(gpu), &gpus::suggest_field_f(gpu))
+    });
+
+    if let Some(path) = state.import_device_path(device) {
+        println!("{} {}", "Device path:".green(), path);
+    }
+
+    if let Some(bytes) = &device.nvcap {
+        input.clear();
+        util::prompt("Load the current NVCAP into the calculator? (y/N): ", &mut input);
+        if input.trim().to_lowercase().starts_with('y') {
+            for warning in state.apply_nvcap_bytes(&vbios.displays, bytes) {
+                println!("{} {}", "Warning:".yellow(), warning);
+            }
+        }
     }
 
     util::press_any_key();
 }
 
-fn draw_nvcap(displays: &Vec<util::Display>) {
+fn choose_merge_strategy(vbios: &mut nvidia::Vbios, saved: &mut Option<session::Session>) {
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("Choose how DCB entries are grouped into displays:");
+        for (i, strategy) in nvidia::MergeStrategy::ALL.iter().enumerate() {
+            let current = if *strategy == vbios.merge { " (current)".green().to_string() } else { String::new() };
+            println!("{} {}{} - {}", format!("({})", i + 1).bright_blue(), strategy.name(), current,
+                strategy.description());
+        }
+
+        if vbios.connectors.is_empty() {
+            println!();
+            println!("{} This ROM has no connector table, so every entry is its own display with {}",
+                "Note:".yellow(), nvidia::MergeStrategy::ConnectorTable.name());
+        }
+
+        println!();
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        util::prompt("Option: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            return;
+        }
+
+        let strategy = match input.parse::<usize>() {
+            Ok(idx) if (1..=nvidia::MergeStrategy::ALL.len()).contains(&idx) => nvidia::MergeStrategy::ALL[idx - 1],
+            _ => continue,
+        };
+
+        if strategy == vbios.merge {
+            return;
+        }
+
+        vbios.set_merge_strategy(strategy);
+
+        // Display indexes changed, but the NVCAP fields and device path still apply
+        if let Some(state) = saved {
+            state.merge = strategy;
+            state.head_tv.clear();
+            state.head_0.clear();
+            state.head_1.clear();
+            filter_displays(&vbios.displays, &mut state.head_tv, &mut state.head_0, &mut state.head_1);
+            println!("{}", "The displays changed, so head assignments were reset".yellow());
+            util::press_any_key();
+        }
+
+        return;
+    }
+}
+
+fn new_session(vbios: &nvidia::Vbios, filename: &str, version_hint: &gpus::Suggestion,
+               field_f_hint: &gpus::Suggestion) -> session::Session {
+    let displays = &vbios.displays;
+
     // Store indexes to displays
     let mut head_tv: Vec<usize> = vec![];
     let mut head_0: Vec<usize> = vec![];
@@ -140,8 +453,8 @@ fn draw_nvcap(displays: &Vec<util::Display>) {
 
     filter_displays(displays, &mut head_tv, &mut head_0, &mut head_1);
 
-    let mut nvcap = nvidia::NVCAP {
-        version: NVCAP_VERSION_MODERN,
+    let nvcap = nvidia::NVCAP {
+        version: version_hint.value,
         is_mobile: util::is_mobile(displays),
         is_composite: util::has_tv(displays),
         unknown_1: 0,
@@ -151,54 +464,217 @@ fn draw_nvcap(displays: &Vec<util::Display>) {
         dcb_2_mask: 0,
         dcb_3_mask: 0,
         script_based_power_and_backlight: false,
-        field_f: 0x0f,
+        field_f: field_f_hint.value,
         edid_bitness: 0,
         unknown_2: [0, 0, 0]
     };
 
+    session::Session {
+        rom_path: filename.to_owned(),
+        rom_crc32: vbios.crc32,
+        merge: vbios.merge,
+        head_tv,
+        head_0,
+        head_1,
+        nvcap,
+        device_path: None,
+    }
+}
+
+fn draw_nvcap(vbios: &nvidia::Vbios, gpu: Option<&gpus::GpuInfo>, filename: &str,
+              saved: &mut Option<session::Session>) {
+    let displays = &vbios.displays;
+
+    let version_hint = gpus::suggest_version(gpu);
+    let field_f_hint = gpus::suggest_field_f(gpu);
+    let edid_bitness_hint = edid::suggest_edid_bitness(displays);
+
+    // Pick up where we left off, if there is anything to pick up
+    let mut state = match saved.take() {
+        Some(state) => state,
+        None => new_session(vbios, filename, &version_hint, &field_f_hint),
+    };
+
+    let mut history = history::History::new();
+
+    if tui::supported() {
+        loop {
+            match tui::run(vbios, &mut state, &mut history, &version_hint, &field_f_hint) {
+                Ok(true) => print_nvcap(vbios, &mut state),
+                Ok(false) => {
+                    *saved = Some(state);
+                    return;
+                }
+                Err(e) => {
+                    // Fall back to the plain menus below
+                    println!("{} {}", "Unable to start the full screen UI:".red(), e);
+                    util::press_any_key();
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut last_nvcap: Option<[u8; nvidia::NVCAP_SIZE]> = None;
+
     loop {
         util::clear_console();
         util::header();
 
-        list_displays(displays, &head_tv, &head_0, &head_1, false);
-        list_options(&nvcap);        
+        list_displays(vbios, &state.head_tv, &state.head_0, &state.head_1, false);
+        list_options(&state.nvcap, &version_hint, &field_f_hint, edid_bitness_hint.as_ref());
+        last_nvcap = Some(print_nvcap_preview(displays, &mut state, last_nvcap.as_ref()));
 
         let mut input = String::new();
         let mut opt: u32 = 0;
 
-        util::prompt("Select one of the above options (1-5, q, or c): ", &mut input);
+        util::prompt("Select one of the above options (1-11, q, s, u, r, h, d, or c): ", &mut input);
         input = input.trim().to_lowercase().to_owned();
-        match input.parse::<u32>() {
-            Ok(val) => { opt = val; }
-            Err(_) => {}
+        if let Ok(val) = input.parse::<u32>() { opt = val; }
+
+        // Head changes are recorded one toggle at a time in choose_heads
+        if opt == 1 {
+            choose_heads(vbios, &mut state, &mut history);
+            continue;
         }
 
+        let before = state.clone();
+        let nvcap = &mut state.nvcap;
         match opt {
-            1 => { choose_heads(displays, &mut head_tv, &mut head_0, &mut head_1) }
             2 => { nvcap.is_mobile = !nvcap.is_mobile }
-            3 => { choose_version(&mut nvcap); }
+            3 => { choose_version(nvcap); }
             4 => { nvcap.is_composite = !nvcap.is_composite }
             5 => { nvcap.script_based_power_and_backlight = !nvcap.script_based_power_and_backlight }
-            6 => { choose_f(&mut nvcap); }
+            6 => { choose_f(nvcap); }
+            7 => { choose_layout(vbios, &state.head_tv, &mut state.head_0, &mut state.head_1); }
+            8 => { choose_byte("Unknown 1", &mut nvcap.unknown_1); }
+            9 => { choose_byte("EDID Bitness", &mut nvcap.edid_bitness); }
+            10 => { choose_unknown_2(nvcap); }
+            11 => { edit_raw_bytes(displays, &mut state); }
             _ => { /* Do nothing */ }
         }
 
+        let description = session::describe_changes(&before, &state);
+        history.record(before, &state, description);
+
         if input.eq("c") {    
-            nvidia::create_nvcap_value(&mut nvcap, displays, &head_tv, &head_0, &head_1);
+            print_nvcap(vbios, &mut state);
+        } else if input.eq("s") {
+            save_session(&state);
+        } else if input.eq("u") {
+            history.undo(&mut state);
+        } else if input.eq("r") {
+            history.redo(&mut state);
+        } else if input.eq("h") {
+            show_history(&history);
+        } else if input.eq("d") {
+            show_display_details(vbios);
         } else if input.eq("q") {
             break;
         } 
     }
+
+    *saved = Some(state);
+}
+
+// Check the head assignments, then print the NVCAP value and config.plist snippets
+fn print_nvcap(vbios: &nvidia::Vbios, state: &mut session::Session) {
+    let displays = &vbios.displays;
+    let issues = validate::check(displays, &vbios.dcb_entries, state.nvcap.is_mobile,
+                                 &state.head_tv, &state.head_0, &state.head_1);
+    if review_issues(&issues) {
+        nvidia::create_nvcap_value(&mut state.nvcap, displays, &state.head_tv, &state.head_0,
+                                   &state.head_1, state.device_path.as_deref());
+    }
+}
+
+// Show what the NVCAP would be right now, returns the bytes shown
+fn print_nvcap_preview(displays: &[util::Display], state: &mut session::Session,
+                       previous: Option<&[u8; nvidia::NVCAP_SIZE]>) -> [u8; nvidia::NVCAP_SIZE] {
+    nvidia::update_head_masks(&mut state.nvcap, displays, &state.head_tv, &state.head_0, &state.head_1);
+    let bytes = state.nvcap.to_bytes();
+
+    println!();
+    println!("{} {}", "NVCAP:".cyan(), nvidia::format_nvcap_hex(&bytes, previous));
+    println!("TV mask: {:#x}  Head 1 mask: {:#x}  Head 2 mask: {:#x}",
+        state.nvcap.dcb_tv_mask, state.nvcap.dcb_0_mask, state.nvcap.dcb_1_mask);
+    println!();
+
+    bytes
+}
+
+fn show_display_details(vbios: &nvidia::Vbios) {
+    util::header();
+    println!("Displays (merged by {}):", vbios.merge.name());
+    println!();
+
+    for (i, display) in vbios.displays.iter().enumerate() {
+        println!("{} {:?}", format!("({})", i + 1).bright_blue(), display.disp_type);
+        println!("    {} {}", "Merged:".green(), display.merge_reason);
+        println!("    {} {}", "Type:".green(), display.type_reason);
+        if let Some(edid) = &display.edid {
+            println!("    {} {}", "EDID:".green(), edid.summary());
+        }
+
+        if let (util::DisplayType::LVDS, Some(lvds)) = (&display.disp_type, &vbios.lvds) {
+            println!("    {}", "Panel:".green());
+            for line in lvds::describe_panel(lvds) {
+                println!("      {}", line);
+            }
+        }
+    }
+
+    println!();
+    println!("Use option {} in the main menu to change how DCB entries are merged", "(5)".cyan());
+    util::press_any_key();
+}
+
+fn show_history(history: &history::History<session::Session>) {
+    util::header();
+    println!("Changes made this session, oldest first:");
+    println!();
+
+    if history.log().is_empty() {
+        println!("Nothing has been changed yet");
+    }
+
+    for (i, change) in history.log().iter().enumerate() {
+        println!("{} {}", format!("({})", i + 1).bright_blue(), change);
+    }
+
+    println!();
+    util::press_any_key();
+}
+
+fn save_session(state: &session::Session) {
+    util::header();
+
+    let default_path = format!("{}.nvcap", state.rom_path);
+    println!("Enter where to save this session, or leave empty to use");
+    println!("{}", default_path.green());
+    println!();
+
+    let mut input = String::new();
+    util::prompt("Session file: ", &mut input);
+    let mut path = input.trim().replace("\"", "");
+    if path.is_empty() {
+        path = default_path;
+    }
+
+    if session::save(&path, state).is_ok() {
+        println!("Saved session to {}", path.green());
+    }
+
+    util::press_any_key();
 }
 
 // Automatically assign displays to heads when they should obviously be there
-fn filter_displays(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>, head_0: &mut Vec<usize>, head_1: &mut Vec<usize>) {
+fn filter_displays(displays: &[util::Display], head_tv: &mut Vec<usize>, head_0: &mut Vec<usize>, head_1: &mut Vec<usize>) {
 
     // If mobile, then the internal display should be on one head with everything else on another head
     let is_mobile = util::is_mobile(displays);
 
-    for i in 0..displays.len() {
-        let display = &displays[i];
+    for (i, display) in displays.iter().enumerate() {
         // Composite/TV can exist on it's own head
         if display.disp_type == util::DisplayType::TV {
             head_tv.push(i);
@@ -209,16 +685,25 @@ fn filter_displays(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>, head
             continue;
         }
 
-        if display.disp_type == util::DisplayType::LVDS &&
+        if display.disp_type.is_internal() &&
            display.head_bitmask & nvidia::HEAD_0_BITMASK != 0 {
             head_0.push(i);
         } else if display.head_bitmask & nvidia::HEAD_1_BITMASK != 0 {
             head_1.push(i);
         }
     }
+
+    // Desktop cards have no internal panel to anchor things, so let the solver split them up
+    if !is_mobile {
+        if let Some(layout) = heads::solve(displays, &[]).into_iter().next() {
+            *head_0 = layout.head_0;
+            *head_1 = layout.head_1;
+        }
+    }
 }
 
-fn list_displays(displays: &Vec<util::Display>, head_tv: &Vec<usize>, head_0: &Vec<usize>, head_1: &Vec<usize>, color: bool) {
+fn list_displays(vbios: &nvidia::Vbios, head_tv: &[usize], head_0: &[usize], head_1: &[usize], color: bool) {
+    let displays = &vbios.displays;
 
     let mut head_tv_out = format!("{} - [", "TV");
     let mut head_1_out = format!("{} - [", "1");
@@ -230,9 +715,8 @@ fn list_displays(displays: &Vec<util::Display>, head_tv: &Vec<usize>, head_0: &V
         head_2_out = format!("{} - [", "2".green());
     }
 
-    println!("Displays:");
-    for i in 0..displays.len() {
-        let display = &displays[i];
+    println!("Displays (merged by {}):", vbios.merge.name());
+    for (i, display) in displays.iter().enumerate() {
         let mut heads = "".to_owned();
 
         for i in 0..4 {
@@ -255,11 +739,11 @@ fn list_displays(displays: &Vec<util::Display>, head_tv: &Vec<usize>, head_0: &V
             print!("{} ", idx_str);
         }
 
-        println!("{} {:?}    {} {}",
-            "Type:",
+        let entries: Vec<String> = display.dcb_entries.iter().map(|entry| format!("{:#x}", entry)).collect();
+        println!("Type: {:?}    \tSupported Heads: {}    \tDCB Entries: {}",
             display.disp_type,
-            "\tSupported Heads:",
-            heads
+            heads,
+            entries.join(", ")
         );
     }
 
@@ -269,9 +753,9 @@ fn list_displays(displays: &Vec<util::Display>, head_tv: &Vec<usize>, head_0: &V
     head_0.iter().for_each(|disp| head_1_out += &format!("{},", disp + 1));
     head_1.iter().for_each(|disp| head_2_out += &format!("{},", disp + 1));
     
-    if head_tv.len() > 0 { head_tv_out = head_tv_out[0..(head_tv_out.len() - 1)].to_owned(); }
-    if head_0.len() > 0 { head_1_out = head_1_out[0..(head_1_out.len() - 1)].to_owned(); }
-    if head_1.len() > 0 { head_2_out = head_2_out[0..(head_2_out.len() - 1)].to_owned(); }
+    if !head_tv.is_empty() { head_tv_out = head_tv_out[0..(head_tv_out.len() - 1)].to_owned(); }
+    if !head_0.is_empty() { head_1_out = head_1_out[0..(head_1_out.len() - 1)].to_owned(); }
+    if !head_1.is_empty() { head_2_out = head_2_out[0..(head_2_out.len() - 1)].to_owned(); }
 
     head_tv_out += "]";
     head_1_out += "]";
@@ -286,19 +770,67 @@ fn list_displays(displays: &Vec<util::Display>, head_tv: &Vec<usize>, head_0: &V
     println!();
 }
 
-fn list_options(nvcap: &nvidia::NVCAP) {
+fn list_options(nvcap: &nvidia::NVCAP, version_hint: &gpus::Suggestion, field_f_hint: &gpus::Suggestion,
+                edid_bitness_hint: Option<&gpus::Suggestion>) {
     println!("{} Add/remove displays from head", "(1)".bright_blue());
     println!("{} Mobile: {}", "(2)".bright_blue(), nvcap.is_mobile);
     println!("{} Version: {}", "(3)".bright_blue(), nvcap.version);
+    print_suggestion(format!("{}", version_hint.value), &version_hint.reason);
     println!("{} Composite: {}", "(4)".bright_blue(),  nvcap.is_composite);
     println!("{} Script Based Power/Backlight: {}", "(5)".bright_blue(),
              nvcap.script_based_power_and_backlight);
     println!("{} Field F: {:#x}", "(6)".bright_blue(),  nvcap.field_f);
+    print_suggestion(format!("{:#x}", field_f_hint.value), &field_f_hint.reason);
+    println!("{} Suggest head layout", "(7)".bright_blue());
+    println!("{} Unknown 1: {:#x}", "(8)".bright_blue(), nvcap.unknown_1);
+    println!("{} EDID Bitness: {:#x}", "(9)".bright_blue(), nvcap.edid_bitness);
+    if let Some(hint) = edid_bitness_hint {
+        print_suggestion(format!("{:#x}", hint.value), &hint.reason);
+    }
+    println!("{} Unknown 2: {:02x} {:02x} {:02x}", "(10)".bright_blue(),
+             nvcap.unknown_2[0], nvcap.unknown_2[1], nvcap.unknown_2[2]);
+    println!("{} Edit raw NVCAP bytes", "(11)".bright_blue());
     println!();
     println!("{} Return to previous menu", "(q)".bright_blue());
+    println!("{} Save session", "(s)".bright_blue());
+    println!("{} Undo, {} Redo, {} Show change history", "(u)".bright_blue(), "(r)".bright_blue(), "(h)".bright_blue());
+    println!("{} Explain how the displays were found", "(d)".bright_blue());
     println!("{} Print out current NVCAP value", "(c)".bright_blue());
 }
 
+// Returns false if the user wants to go back and fix errors
+fn review_issues(issues: &[validate::Issue]) -> bool {
+    if issues.is_empty() {
+        return true;
+    }
+
+    util::header();
+    println!("Found some problems with the current head assignments:");
+    println!();
+
+    for issue in issues {
+        match issue.severity {
+            validate::Severity::Error => println!("{} {}", "Error:".red(), issue.message),
+            validate::Severity::Warning => println!("{} {}", "Warning:".yellow(), issue.message),
+        }
+    }
+
+    println!();
+
+    if !issues.iter().any(|issue| issue.severity == validate::Severity::Error) {
+        util::press_any_key();
+        return true;
+    }
+
+    let mut input = String::new();
+    util::prompt("Print the NVCAP value anyway? (y/N): ", &mut input);
+    input.trim().to_lowercase().starts_with('y')
+}
+
+fn print_suggestion(value: String, reason: &str) {
+    println!("      {}", format!("Suggested {}: {}", value, reason).dimmed());
+}
+
 fn toggle_display(head: &mut Vec<usize>, disp_idx: usize) {
     match head.iter().position(|&x| x == disp_idx) {
         Some(idx) => { head.remove(idx); }
@@ -306,8 +838,9 @@ fn toggle_display(head: &mut Vec<usize>, disp_idx: usize) {
     }
 }
 
-fn choose_heads(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>,
-                head_0: &mut Vec<usize>, head_1: &mut Vec<usize>) {
+fn choose_heads(vbios: &nvidia::Vbios, state: &mut session::Session,
+                history: &mut history::History<session::Session>) {
+    let displays = &vbios.displays;
     let has_tv = util::has_tv(displays);
     
     loop {
@@ -316,7 +849,7 @@ fn choose_heads(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>,
         let mut head: usize = 0;
 
         util::header();
-        list_displays(displays, head_tv, head_0, head_1, true);
+        list_displays(vbios, &state.head_tv, &state.head_0, &state.head_1, true);
         
         println!("Select a display and head to add/remove it from the chosen head");
         println!("The input should look like \"{} {}\"", "<display>".bright_blue(), "<head>".green());
@@ -343,7 +876,7 @@ fn choose_heads(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>,
             Err(_) => { continue; }
         }
 
-        if disp_idx > displays.len() {
+        if disp_idx == 0 || disp_idx > displays.len() {
             // Out of index display
             continue;
         }
@@ -357,6 +890,7 @@ fn choose_heads(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>,
         // Vec is 0 based idx, we start at 1 when displaying though
         disp_idx -= 1;
         let disp = &displays[disp_idx];
+        let before = state.clone();
 
         match head {
             1 => {
@@ -364,22 +898,99 @@ fn choose_heads(displays: &Vec<util::Display>, head_tv: &mut Vec<usize>,
                     continue;
                 } 
 
-                toggle_display(head_0, disp_idx); }
+                toggle_display(&mut state.head_0, disp_idx); }
             2 => { 
                 if disp.head_bitmask & nvidia::HEAD_1_BITMASK == 0 {
                     continue;
                 }
                 
-                toggle_display(head_1, disp_idx); }
+                toggle_display(&mut state.head_1, disp_idx); }
             _ => {
                 // Maybe TV
                 if !has_tv || !args[1].eq("tv") {
                     continue;
                 }
 
-                toggle_display(head_tv, disp_idx);
+                toggle_display(&mut state.head_tv, disp_idx);
              }
         }
+
+        let description = session::describe_changes(&before, state);
+        history.record(before, state, description);
+    }
+}
+
+fn print_layout(displays: &[util::Display], layout: &heads::Layout) {
+    let names = |head: &Vec<usize>| head.iter()
+        .map(|&idx| format!("({}) {:?}", idx + 1, displays[idx].disp_type))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    println!("    Head 1: [{}]", names(&layout.head_0));
+    println!("    Head 2: [{}]", names(&layout.head_1));
+    for note in &layout.notes {
+        println!("      {}", note.dimmed());
+    }
+}
+
+fn choose_layout(vbios: &nvidia::Vbios, head_tv: &[usize], head_0: &mut Vec<usize>, head_1: &mut Vec<usize>) {
+    let displays = &vbios.displays;
+    util::header();
+    list_displays(vbios, head_tv, head_0, head_1, true);
+
+    println!("List the displays you use most, highest priority first (ie. \"2 1\")");
+    println!("Leave empty if you have no preference");
+    println!();
+
+    let mut input = String::new();
+    util::prompt("Priority displays: ", &mut input);
+
+    let priorities: Vec<usize> = input.split_whitespace()
+        .filter_map(|arg| arg.parse::<usize>().ok())
+        .filter(|&idx| idx >= 1 && idx <= displays.len())
+        .map(|idx| idx - 1)
+        .collect();
+
+    let layouts = heads::solve(displays, &priorities);
+    if layouts.is_empty() {
+        println!("{}", "No valid layout for these displays".red());
+        util::press_any_key();
+        return;
+    }
+
+    loop {
+        util::header();
+
+        for (i, layout) in layouts.iter().enumerate() {
+            let label = format!("({})", i + 1);
+            if i == 0 {
+                println!("{} Suggested layout (score {})", label.bright_blue(), layout.score);
+            } else {
+                println!("{} Alternative (score {}, {} less than suggested)", label.bright_blue(),
+                    layout.score, layouts[0].score - layout.score);
+            }
+            print_layout(displays, layout);
+            println!();
+        }
+
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        let mut input = String::new();
+        util::prompt("Select a layout to use: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            break;
+        }
+
+        if let Ok(idx) = input.parse::<usize>() {
+            if idx >= 1 && idx <= layouts.len() {
+                let layout = &layouts[idx - 1];
+                *head_0 = layout.head_0.clone();
+                *head_1 = layout.head_1.clone();
+                break;
+            }
+        }
     }
 }
 
@@ -410,6 +1021,102 @@ fn choose_f(nvcap: &mut nvidia::NVCAP) {
     }
 }
 
+// For the fields nobody knows the meaning of yet, any value goes
+fn choose_byte(name: &str, field: &mut u8) {
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("Select {} field (currently {:#x})", name, field);
+        println!("The purpose of this field is unknown, use at your own risk");
+        println!();
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        util::prompt("New Value: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            break;
+        }
+
+        let no_prefix = input.trim_start_matches("0x");
+
+        match u8::from_str_radix(no_prefix, 16) {
+            Ok(val) => { *field = val; break; }
+            Err(_) => { continue; }
+        }
+    }
+}
+
+fn choose_unknown_2(nvcap: &mut nvidia::NVCAP) {
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("Select Unknown 2 field (currently {:02x} {:02x} {:02x})",
+            nvcap.unknown_2[0], nvcap.unknown_2[1], nvcap.unknown_2[2]);
+        println!("Enter all 3 bytes in hex, ie. \"00 00 00\"");
+        println!();
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        util::prompt("New Value: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            break;
+        }
+
+        match util::parse_hex_bytes(&input) {
+            Some(bytes) if bytes.len() == nvcap.unknown_2.len() => {
+                nvcap.unknown_2.copy_from_slice(&bytes);
+                break;
+            }
+            _ => { continue; }
+        }
+    }
+}
+
+fn edit_raw_bytes(displays: &[util::Display], state: &mut session::Session) {
+    let mut warnings: Vec<String> = vec![];
+
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        nvidia::update_head_masks(&mut state.nvcap, displays, &state.head_tv, &state.head_0, &state.head_1);
+        let bytes = state.nvcap.to_bytes();
+
+        println!("NVCAP bytes:");
+        for (offset, byte) in bytes.iter().enumerate() {
+            println!("{} {:02x}  {}", format!("({:02x})", offset).bright_blue(), byte,
+                nvidia::nvcap_field_name(offset).dimmed());
+        }
+
+        println!();
+        for warning in &warnings {
+            println!("{} {}", "Warning:".yellow(), warning);
+        }
+
+        println!("Enter \"{} {}\" to change one byte (ie. \"f 0b\"),", "<offset>".bright_blue(), "<value>".green());
+        println!("or all {} bytes to replace the whole NVCAP value", nvidia::NVCAP_SIZE);
+        println!("Head masks are turned back into display assignments");
+        println!();
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        util::prompt("Bytes: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            break;
+        }
+
+        warnings = match state.apply_raw_input(displays, &input) {
+            Ok(warnings) => warnings,
+            Err(error) => vec![error],
+        };
+    }
+}
+
 fn choose_version(nvcap: &mut nvidia::NVCAP) {
     loop {
         let mut input = String::new();
@@ -435,4 +1142,67 @@ fn choose_version(nvcap: &mut nvidia::NVCAP) {
             Err(_) => { continue; }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn nvcap_hex(rom: &[u8]) -> String {
+        let vbios = nvidia::parse_rom(rom).unwrap();
+        suggested_nvcap(&vbios, "test.rom").iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn desktop_nvcap() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(CRT, 2, 3, 2, 2))
+            .entry(dcb_entry(TV, 0xf, 3, 3, 3))
+            .build();
+
+        assert_eq!(nvcap_hex(&rom), "05000100100003000c0000000000000a00000000");
+    }
+
+    #[test]
+    fn laptop_nvcap() {
+        let rom = RomBuilder::new()
+            .pci(0x10de, 0x0609)
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(CRT, 1, 3, 1, 1))
+            .entry(dcb_entry(TV, 0xf, 3, 2, 2))
+            .build();
+
+        assert_eq!(nvcap_hex(&rom), "0501010008000100060000000000000b00000000");
+    }
+
+    #[test]
+    fn edp_laptop_nvcap() {
+        let rom = RomBuilder::new()
+            .pci(0x10de, 0x0a29)
+            .entry(dcb_entry(DP, 0, 1, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(DP, 2, 3, 2, 2))
+            .connector(CONN_EDP)
+            .connector(CONN_HDMI)
+            .connector(CONN_DP)
+            .build();
+
+        assert_eq!(nvcap_hex(&rom), "0501000000000100060000000000000f00000000");
+    }
+
+    #[test]
+    fn unknown_gpu_nvcap() {
+        let rom = RomBuilder::new()
+            .no_pci()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .build();
+
+        assert_eq!(nvcap_hex(&rom), "0500000000000100020000000000000f00000000");
+    }
+}
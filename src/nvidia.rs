@@ -1,6 +1,10 @@
 
 use colored::*;
-use std::{fs, mem};
+use std::fs;
+use crate::bit::{self, Bit};
+use crate::bmp::{self, Bmp};
+use crate::export;
+use crate::lvds::{self, LvdsTables};
 use crate::util::{self, NVErrors};
 
 // DCB numbers
@@ -10,20 +14,41 @@ const DCB_MIN_VERSION: u8   = 0x30;
 
 const DCB_HEADER_ADDR: usize        = 0x36;
 
+// PCI expansion ROM numbers
+const PCI_ROM_SIGNATURE: u16        = 0xaa55;
+const PCIR_SIGNATURE: u32           = 0x52494350; // "PCIR"
+const PCIR_POINTER_ADDR: usize      = 0x18;
+
+// PCIR offsets
+const PCIR_VENDOR_OFFSET: usize     = 0x4;
+const PCIR_DEVICE_OFFSET: usize     = 0x6;
+const PCIR_LENGTH_OFFSET: usize     = 0xa;
+
 // DCB offsets
 const DCB_SIZE_OFFSET: usize        = 0x1;
 const DCB_ENTRY_COUNT_OFFSET: usize = 0x2;
 const DCB_ENTRY_SIZE_OFFSET: usize  = 0x3;
 const DCB_SIGNATURE_OFFSET: usize   = 0x6;
+// Table pointers and flags, only there if the header is long enough to hold them
+const DCB_I2C_TABLE_OFFSET: usize       = 0x4;
+const DCB_GPIO_TABLE_OFFSET: usize      = 0xa;
+const DCB_CONNECTOR_TABLE_OFFSET: usize = 0x14;
+const DCB_FLAGS_OFFSET: usize           = 0x16;
+
+// Connector table header
+const CONN_TABLE_SIZE_OFFSET: usize        = 0x1;
+const CONN_TABLE_ENTRY_COUNT_OFFSET: usize = 0x2;
+const CONN_TABLE_ENTRY_SIZE_OFFSET: usize  = 0x3;
 
 // DCB connector types
 const DCB_CONN_CRT: u32     = 0; // VGA
 const DCB_CONN_TV: u32      = 1; // Composite
-const DCB_CONN_TMDS: u32    = 2; // DVI, HDMI, etc
-const DCB_CONN_LVDS: u32    = 3; // Laptop Disp
+pub const DCB_CONN_TMDS: u32    = 2; // DVI, HDMI, etc
+pub const DCB_CONN_LVDS: u32    = 3; // Laptop Disp
 const DCB_CONN_SDI: u32     = 5; // SDI
-const DCB_CONN_DP: u32      = 6; // DisplayPort
+pub const DCB_CONN_DP: u32      = 6; // DisplayPort
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct DcbEntry {
     pub entry_type: u32,
     pub edid_port: u32,
@@ -37,6 +62,238 @@ pub struct DcbEntry {
     pub entry_is_virtual: bool,
     pub reserved: u32,
     pub entry: u8,
+    // Where the entry lives in the ROM, so it can be written back
+    pub offset: usize,
+}
+
+pub struct PciHeader {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    // Where the PCIR structure is, and how long it is
+    pub offset: usize,
+    pub length: usize,
+}
+
+// https://nvidia.github.io/open-gpu-doc/DCB/DCB-4.x-Specification.html#_dcb_header
+pub struct DcbHeader {
+    pub offset: usize,
+    pub version: u8,
+    pub size: usize,
+    pub entry_count: u8,
+    pub entry_size: u8,
+    // None if the header is too short to hold them, or the pointer is 0
+    pub i2c_table: Option<usize>,
+    pub gpio_table: Option<usize>,
+    pub connector_table: Option<usize>,
+    pub flags: Option<u8>,
+}
+
+// https://nvidia.github.io/open-gpu-doc/DCB/DCB-4.x-Specification.html#_connector_table
+// The physical connector each DCB entry's connector index points at
+pub struct Connector {
+    pub index: u8,
+    pub conn_type: u8,
+    pub raw: u32,
+}
+
+pub const CONNECTOR_NONE: u8 = 0xff;
+
+const CONN_LOCATION_BITS: (u32, u32) = (8, 4);
+
+impl Connector {
+    pub fn location(&self) -> u32 {
+        get_bits(self.raw, CONN_LOCATION_BITS)
+    }
+}
+
+// How DCB entries are grouped into the displays shown to the user
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    Bus,
+    Connector,
+    ConnectorTable,
+    None,
+}
+
+impl MergeStrategy {
+    pub const ALL: [MergeStrategy; 4] = [
+        MergeStrategy::Bus, MergeStrategy::Connector, MergeStrategy::ConnectorTable, MergeStrategy::None,
+    ];
+
+    // Short name, also used in session files
+    pub fn name(&self) -> &'static str {
+        match self {
+            MergeStrategy::Bus => "bus",
+            MergeStrategy::Connector => "connector",
+            MergeStrategy::ConnectorTable => "connector-table",
+            MergeStrategy::None => "none",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MergeStrategy> {
+        MergeStrategy::ALL.iter().copied().find(|strategy| strategy.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            MergeStrategy::Bus => "Entries with the same bus index are one display",
+            MergeStrategy::Connector => "Entries with the same connector index are one display",
+            MergeStrategy::ConnectorTable => "Entries pointing at the same connector table entry are one display",
+            MergeStrategy::None => "Every DCB entry is its own display",
+        }
+    }
+}
+
+// Everything we pulled out of a VBIOS file
+pub struct Vbios {
+    pub rom: Vec<u8>,
+    pub crc32: u32,
+    pub pci: Option<PciHeader>,
+    pub bit: Option<Bit>,
+    pub bmp: Option<Bmp>,
+    pub lvds: Option<LvdsTables>,
+    pub dcb: DcbHeader,
+    pub dcb_entries: Vec<DcbEntry>,
+    pub connectors: Vec<Connector>,
+    pub merge: MergeStrategy,
+    pub displays: Vec<util::Display>,
+}
+
+impl Vbios {
+    // Regroup the DCB entries, display indexes from before are no longer valid
+    pub fn set_merge_strategy(&mut self, strategy: MergeStrategy) {
+        let old_displays = std::mem::take(&mut self.displays);
+        self.merge = strategy;
+        merge_dcb_entries(&self.dcb_entries, &self.connectors, strategy, &mut self.displays);
+
+        // Attached EDIDs follow their DCB entries into the new displays
+        for old in old_displays {
+            let entries = old.dcb_entries;
+            if let (Some(edid), Some(display)) = (old.edid, self.displays.iter_mut()
+                .find(|display| display.edid.is_none() && display.dcb_entries.iter().any(|entry| entries.contains(entry)))) {
+                display.edid = Some(edid);
+            }
+        }
+    }
+}
+
+// PCI option ROM image size is stored in 512 byte blocks
+const PCI_ROM_SIZE_ADDR: usize = 0x2;
+const PCI_ROM_BLOCK_SIZE: usize = 512;
+
+pub const DCB_TYPE_SKIP: u32 = 0xf;
+
+// https://nvidia.github.io/open-gpu-doc/DCB/DCB-4.x-Specification.html#_dcb_device_entries
+// Shift and width of each field in the first 32 bits of a DCB entry
+const DCB_TYPE_BITS: (u32, u32)      = (0, 4);
+const DCB_EDID_PORT_BITS: (u32, u32) = (4, 4);
+const DCB_HEAD_BITS: (u32, u32)      = (8, 4);
+const DCB_CON_BITS: (u32, u32)       = (12, 4);
+const DCB_BUS_BITS: (u32, u32)       = (16, 4);
+const DCB_LOC_BITS: (u32, u32)       = (20, 2);
+const DCB_BDR_BITS: (u32, u32)       = (22, 1);
+const DCB_BBDR_BITS: (u32, u32)      = (23, 1);
+const DCB_RESOURCES_BITS: (u32, u32) = (24, 4);
+const DCB_VIRTUAL_BITS: (u32, u32)   = (28, 1);
+const DCB_RESERVED_BITS: (u32, u32)  = (29, 3);
+
+// Short name and bits of every field, highest bits first
+pub const DCB_FIELDS: [(&str, (u32, u32)); 11] = [
+    ("Rsvd", DCB_RESERVED_BITS),
+    ("Virt", DCB_VIRTUAL_BITS),
+    ("Res", DCB_RESOURCES_BITS),
+    ("BBDR", DCB_BBDR_BITS),
+    ("BDR", DCB_BDR_BITS),
+    ("Loc", DCB_LOC_BITS),
+    ("Bus", DCB_BUS_BITS),
+    ("Con", DCB_CON_BITS),
+    ("Head", DCB_HEAD_BITS),
+    ("Edid", DCB_EDID_PORT_BITS),
+    ("Type", DCB_TYPE_BITS),
+];
+
+pub fn get_bits(conn: u32, (shift, width): (u32, u32)) -> u32 {
+    (conn >> shift) & ((1 << width) - 1)
+}
+
+fn put_bits(value: u32, (shift, width): (u32, u32)) -> u32 {
+    (value & ((1 << width) - 1)) << shift
+}
+
+impl DcbEntry {
+    // Split the first 32 bits of a DCB entry into its fields
+    pub fn decode(conn: u32, entry: u8, offset: usize) -> DcbEntry {
+        DcbEntry {
+            entry_type: get_bits(conn, DCB_TYPE_BITS),
+            edid_port: get_bits(conn, DCB_EDID_PORT_BITS),
+            head_bitmask: get_bits(conn, DCB_HEAD_BITS),
+            con: get_bits(conn, DCB_CON_BITS),
+            bus: get_bits(conn, DCB_BUS_BITS),
+            loc: get_bits(conn, DCB_LOC_BITS),
+            bdr: get_bits(conn, DCB_BDR_BITS),
+            bbdr: get_bits(conn, DCB_BBDR_BITS),
+            output_resources: get_bits(conn, DCB_RESOURCES_BITS),
+            entry_is_virtual: get_bits(conn, DCB_VIRTUAL_BITS) == 1,
+            reserved: get_bits(conn, DCB_RESERVED_BITS),
+            entry,
+            offset,
+        }
+    }
+
+    // Inverse of decode, back to the first 32 bits of the entry
+    pub fn encode(&self) -> u32 {
+        put_bits(self.entry_type, DCB_TYPE_BITS) |
+            put_bits(self.edid_port, DCB_EDID_PORT_BITS) |
+            put_bits(self.head_bitmask, DCB_HEAD_BITS) |
+            put_bits(self.con, DCB_CON_BITS) |
+            put_bits(self.bus, DCB_BUS_BITS) |
+            put_bits(self.loc, DCB_LOC_BITS) |
+            put_bits(self.bdr, DCB_BDR_BITS) |
+            put_bits(self.bbdr, DCB_BBDR_BITS) |
+            put_bits(self.output_resources, DCB_RESOURCES_BITS) |
+            put_bits(self.entry_is_virtual as u32, DCB_VIRTUAL_BITS) |
+            put_bits(self.reserved, DCB_RESERVED_BITS)
+    }
+}
+
+// Write entries back to where they were read from
+pub fn write_dcb_entries(rom: &mut [u8], entries: &[DcbEntry]) {
+    for entry in entries {
+        rom[entry.offset..entry.offset + 4].copy_from_slice(&entry.encode().to_le_bytes());
+    }
+}
+
+// The bytes of a PCI option ROM image have to add up to 0, which the last byte of the
+// image makes up for. Returns the new checksum byte, or None if the image size is bad.
+pub fn fix_checksum(rom: &mut [u8]) -> Option<u8> {
+    let size = *rom.get(PCI_ROM_SIZE_ADDR)? as usize * PCI_ROM_BLOCK_SIZE;
+    if size == 0 || size > rom.len() || util::read_uint_16_le(rom, 0) != PCI_ROM_SIGNATURE {
+        return None;
+    }
+
+    let sum = rom[..size - 1].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    rom[size - 1] = 0u8.wrapping_sub(sum);
+    Some(rom[size - 1])
+}
+
+// https://github.com/torvalds/linux/blob/master/drivers/gpu/drm/nouveau/include/nvkm/subdev/bios/conn.h
+pub fn connector_type_to_string(conn_type: u8) -> &'static str {
+    match conn_type {
+        0x00 => "VGA",
+        0x10..=0x13 => "TV",
+        0x20 => "Component",
+        0x30 => "DVI-I",
+        0x31 => "DVI-D",
+        0x38 | 0x39 => "DMS-59",
+        0x40 | 0x41 => "LVDS",
+        0x46 => "DisplayPort",
+        0x47 => "eDP",
+        0x48 => "Mini DisplayPort",
+        0x60 | 0x61 | 0x63 => "HDMI",
+        0x64 | 0x65 => "DMS-59 DisplayPort",
+        CONNECTOR_NONE => "None",
+        _ => "Unknown",
+    }
 }
 
 pub fn dcb_type_to_string(dcb_type: u32) -> &'static str {
@@ -55,10 +312,14 @@ pub fn dcb_type_to_string(dcb_type: u32) -> &'static str {
 // https://github.com/acidanthera/WhateverGreen/blob/master/Manual/NVCAP.bt#L9
 // Starting from 8000 series
 pub const NVCAP_VERSION_MODERN: u8 = 5;
+// 6000 and 7000 series
+pub const NVCAP_VERSION_LEGACY: u8 = 4;
 pub const HEAD_0_BITMASK: u32 = 0x1;
 pub const HEAD_1_BITMASK: u32 = 0x2;
 
 #[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct NVCAP {
     pub version: u8,
     pub is_mobile: bool,
@@ -86,7 +347,71 @@ pub struct NVCAP {
     pub unknown_2: [u8; 3],
 }
 
-pub fn parse_signature(version: u8, rom: &Vec<u8>, offset: usize) -> bool {
+pub const NVCAP_SIZE: usize = 20;
+
+impl NVCAP {
+    // Byte layout macOS expects, same as the in-memory layout of this struct
+    pub fn to_bytes(self) -> [u8; NVCAP_SIZE] {
+        let mut bytes = [0u8; NVCAP_SIZE];
+        let masks = [self.dcb_tv_mask, self.dcb_0_mask, self.dcb_1_mask, self.dcb_2_mask, self.dcb_3_mask];
+
+        bytes[0] = self.version;
+        bytes[1] = self.is_mobile as u8;
+        bytes[2] = self.is_composite as u8;
+        bytes[3] = self.unknown_1;
+        for (i, mask) in masks.iter().enumerate() {
+            bytes[4 + i * 2..6 + i * 2].copy_from_slice(&mask.to_le_bytes());
+        }
+        bytes[14] = self.script_based_power_and_backlight as u8;
+        bytes[15] = self.field_f;
+        bytes[16] = self.edid_bitness;
+        bytes[17..].copy_from_slice(&self.unknown_2);
+
+        bytes
+    }
+
+    // Inverse of to_bytes. Boolean fields treat any non-zero byte as true.
+    pub fn from_bytes(bytes: &[u8; NVCAP_SIZE]) -> NVCAP {
+        let mask = |i: usize| u16::from_le_bytes([bytes[4 + i * 2], bytes[5 + i * 2]]);
+
+        NVCAP {
+            version: bytes[0],
+            is_mobile: bytes[1] != 0,
+            is_composite: bytes[2] != 0,
+            unknown_1: bytes[3],
+            dcb_tv_mask: mask(0),
+            dcb_0_mask: mask(1),
+            dcb_1_mask: mask(2),
+            dcb_2_mask: mask(3),
+            dcb_3_mask: mask(4),
+            script_based_power_and_backlight: bytes[14] != 0,
+            field_f: bytes[15],
+            edid_bitness: bytes[16],
+            unknown_2: [bytes[17], bytes[18], bytes[19]],
+        }
+    }
+}
+
+// Name of the NVCAP field each byte belongs to
+pub fn nvcap_field_name(offset: usize) -> &'static str {
+    match offset {
+        0 => "Version",
+        1 => "Mobile",
+        2 => "Composite",
+        3 => "Unknown 1",
+        4 | 5 => "TV mask",
+        6 | 7 => "Head 1 mask",
+        8 | 9 => "Head 2 mask",
+        10 | 11 => "Head 3 mask",
+        12 | 13 => "Head 4 mask",
+        14 => "Script Based Power/Backlight",
+        15 => "Field F",
+        16 => "EDID Bitness",
+        _ => "Unknown 2",
+    }
+}
+
+pub fn parse_signature(version: u8, rom: &[u8], offset: usize) -> bool {
     if version >= DCB_MAX_VERSION {
         println!("Unknown version");
         return false;
@@ -105,30 +430,88 @@ pub fn parse_signature(version: u8, rom: &Vec<u8>, offset: usize) -> bool {
     true
 }
 
-fn parse_dcb_entries(rom: &Vec<u8>, offset: usize, dcb_size: usize, parsed_entries: &mut Vec<DcbEntry>) {
-    let dcb_entries: u8 = rom[offset + DCB_ENTRY_COUNT_OFFSET];
-    let dcb_entry_size: u8 = rom[offset + DCB_ENTRY_SIZE_OFFSET];
+fn has_dcb_signature(rom: &[u8], offset: usize) -> bool {
+    offset + DCB_SIGNATURE_OFFSET + 4 <= rom.len() &&
+        util::read_uint_32_le(rom, offset + DCB_SIGNATURE_OFFSET) == DCB_SIGNATURE
+}
+
+// Some ROMs don't have a good DCB pointer, so look for the signature instead
+fn find_dcb_header(rom: &[u8]) -> Option<usize> {
+    (DCB_SIGNATURE_OFFSET..rom.len().saturating_sub(3))
+        .filter(|&pos| util::read_uint_32_le(rom, pos) == DCB_SIGNATURE)
+        .map(|pos| pos - DCB_SIGNATURE_OFFSET)
+        .find(|&offset| (DCB_MIN_VERSION..DCB_MAX_VERSION).contains(&rom[offset]))
+}
+
+// https://pcisig.com/specifications - PCI Firmware Spec, Expansion ROM header
+fn parse_pci_header(rom: &[u8]) -> Option<PciHeader> {
+    if rom.len() < PCIR_POINTER_ADDR + 2 || util::read_uint_16_le(rom, 0) != PCI_ROM_SIGNATURE {
+        return None;
+    }
+
+    let pcir_offset = util::read_uint_16_le(rom, PCIR_POINTER_ADDR) as usize;
+    if rom.len() < pcir_offset + PCIR_LENGTH_OFFSET + 2 ||
+       util::read_uint_32_le(rom, pcir_offset) != PCIR_SIGNATURE {
+        return None;
+    }
+
+    Some(PciHeader {
+        vendor_id: util::read_uint_16_le(rom, pcir_offset + PCIR_VENDOR_OFFSET),
+        device_id: util::read_uint_16_le(rom, pcir_offset + PCIR_DEVICE_OFFSET),
+        offset: pcir_offset,
+        length: util::read_uint_16_le(rom, pcir_offset + PCIR_LENGTH_OFFSET) as usize,
+    })
+}
+
+fn dcb_header_pointer(rom: &[u8], offset: usize, dcb_size: usize, field: usize) -> Option<usize> {
+    if dcb_size < field + 2 || offset + field + 2 > rom.len() {
+        return None;
+    }
+
+    match util::read_uint_16_le(rom, offset + field) {
+        0 => None,
+        pointer => Some(pointer as usize),
+    }
+}
+
+// Callers have already checked the signature is inside the ROM
+fn parse_dcb_header(rom: &[u8], offset: usize) -> DcbHeader {
+    let size = rom[offset + DCB_SIZE_OFFSET] as usize;
+    let flags = if size > DCB_FLAGS_OFFSET && offset + DCB_FLAGS_OFFSET < rom.len() {
+        Some(rom[offset + DCB_FLAGS_OFFSET])
+    } else {
+        None
+    };
+
+    DcbHeader {
+        offset,
+        version: rom[offset],
+        size,
+        entry_count: rom[offset + DCB_ENTRY_COUNT_OFFSET],
+        entry_size: rom[offset + DCB_ENTRY_SIZE_OFFSET],
+        i2c_table: dcb_header_pointer(rom, offset, size, DCB_I2C_TABLE_OFFSET),
+        gpio_table: dcb_header_pointer(rom, offset, size, DCB_GPIO_TABLE_OFFSET),
+        connector_table: dcb_header_pointer(rom, offset, size, DCB_CONNECTOR_TABLE_OFFSET),
+        flags,
+    }
+}
+
+fn parse_dcb_entries(rom: &[u8], dcb: &DcbHeader, parsed_entries: &mut Vec<DcbEntry>) {
+    let dcb_entries: u8 = dcb.entry_count;
+    let dcb_entry_size: u8 = dcb.entry_size;
 
     println!("{} DCB entries of size {:#x} bytes\n", dcb_entries, dcb_entry_size);
 
     for number in 0..dcb_entries {
-        let entry_offset = offset + dcb_size + (dcb_entry_size * number) as usize;
+        let entry_offset = dcb.offset + dcb.size + dcb_entry_size as usize * number as usize;
+        if entry_offset + 4 > rom.len() {
+            println!("{} DCB entry {} is past the end of the ROM", "Warning:".yellow(), number);
+            break;
+        }
+
         let conn: u32 = util::read_uint_32_le(rom, entry_offset);
         
-        let dcb_head = DcbEntry {
-            entry_type: conn & 0xf,
-            edid_port: (conn >> 4) & 0xf,
-            head_bitmask: (conn >> 8) & 0xf,
-            con: (conn >> 12) & 0xf,
-            bus: (conn >> 16) & 0xf,
-            loc: (conn >> 20) & 0x3,
-            bdr: (conn >> 22) & 0x1,
-            bbdr: (conn >> 23) & 0x1,
-            output_resources: (conn >> 24) & 0xf,
-            entry_is_virtual: ((conn >> 28) & 0x1) == 1,
-            reserved: (conn >> 28) & 0x7,
-            entry: number,
-        };
+        let dcb_head = DcbEntry::decode(conn, number, entry_offset);
 
         // Skip entry
         if dcb_head.entry_type == 0xf {
@@ -146,17 +529,127 @@ fn parse_dcb_entries(rom: &Vec<u8>, offset: usize, dcb_size: usize, parsed_entri
     println!("Found {} populated DCB Entries", parsed_entries.len());
 }
 
-fn get_display_type(entry: &DcbEntry) -> util::DisplayType {
-    match entry.entry_type {
+fn entry_display_type(entry_type: u32) -> util::DisplayType {
+    match entry_type {
         DCB_CONN_LVDS => util::DisplayType::LVDS,
         DCB_CONN_CRT => util::DisplayType::Analog,
         DCB_CONN_TV => util::DisplayType::TV,
-        _ => util::DisplayType::Digital,
+        DCB_CONN_TMDS => util::DisplayType::TMDS,
+        DCB_CONN_SDI => util::DisplayType::SDI,
+        DCB_CONN_DP => util::DisplayType::DisplayPort,
+        _ => util::DisplayType::Unknown(entry_type),
+    }
+}
+
+// Connector table entry a DCB entry's connector index points at
+fn connector_of<'a>(entry: &DcbEntry, connectors: &'a [Connector]) -> Option<&'a Connector> {
+    connectors.iter().find(|conn| conn.index as u32 == entry.con)
+}
+
+// Laptop panels, either LVDS outputs or DisplayPort on an eDP connector
+pub fn entry_is_internal(entry: &DcbEntry, connectors: &[Connector]) -> bool {
+    let connector = connector_of(entry, connectors)
+        .and_then(|conn| connector_display_type(conn.conn_type));
+
+    entry.entry_type == DCB_CONN_LVDS || connector.is_some_and(|disp_type| disp_type.is_internal())
+}
+
+// The physical connector says more than the output type, ie. TMDS on an HDMI port
+fn connector_display_type(conn_type: u8) -> Option<util::DisplayType> {
+    match conn_type {
+        0x00 => Some(util::DisplayType::Analog),
+        0x10..=0x13 | 0x20 => Some(util::DisplayType::TV),
+        0x30 | 0x38 | 0x39 => Some(util::DisplayType::DVI),
+        0x31 => Some(util::DisplayType::TMDS),
+        0x40 | 0x41 => Some(util::DisplayType::LVDS),
+        0x46 | 0x48 | 0x64 | 0x65 => Some(util::DisplayType::DisplayPort),
+        0x47 => Some(util::DisplayType::EDP),
+        0x60 | 0x61 | 0x63 => Some(util::DisplayType::HDMI),
+        _ => None,
+    }
+}
+
+fn get_display_type(group: &[&DcbEntry], connectors: &[Connector]) -> (util::DisplayType, String) {
+    let first = group[0];
+
+    // Only trust the connector table if the whole display is on one connector
+    if group.iter().all(|x| x.con == first.con) {
+        if let Some(conn) = connector_of(first, connectors) {
+            if let Some(disp_type) = connector_display_type(conn.conn_type) {
+                let reason = format!("connector table entry {} ({}) is shown as {:?}",
+                    conn.index, connector_type_to_string(conn.conn_type), disp_type);
+                return (disp_type, reason);
+            }
+        }
+    }
+
+    if group.iter().all(|x| x.entry_type == first.entry_type) {
+        let disp_type = entry_display_type(first.entry_type);
+        let reason = format!("{} outputs are shown as {:?}", dcb_type_to_string(first.entry_type), disp_type);
+        return (disp_type, reason);
+    }
+
+    // Dual mode DisplayPort carries TMDS as well, ie. DP and TMDS on one Mini DisplayPort
+    if group.iter().any(|x| x.entry_type == DCB_CONN_DP) {
+        return (util::DisplayType::DisplayPort,
+            "DisplayPort with other outputs is shown as DisplayPort (dual mode)".to_owned());
+    }
+
+    // Different outputs on one connector, ie. TMDS and CRT on DVI-I
+    (util::DisplayType::DVI, "different output types on one display are shown as DVI".to_owned())
+}
+
+fn merge_key(entry: &DcbEntry, connectors: &[Connector], strategy: MergeStrategy) -> Option<u32> {
+    match strategy {
+        MergeStrategy::Bus => Some(entry.bus),
+        MergeStrategy::Connector => Some(entry.con),
+        // Entries without a usable connector table entry can't be matched up. Two table entries
+        // can describe identical sockets (ie. two VGA ports without hotplug), so only the index counts.
+        MergeStrategy::ConnectorTable => connector_of(entry, connectors)
+            .filter(|conn| conn.conn_type != CONNECTOR_NONE)
+            .map(|_| entry.con),
+        MergeStrategy::None => None,
+    }
+}
+
+// ie. "entries 0x0 (TMDS) and 0x1 (CRT)"
+fn describe_entries(group: &[&DcbEntry]) -> String {
+    let names: Vec<String> = group.iter()
+        .map(|x| format!("{:#x} ({})", x.entry, dcb_type_to_string(x.entry_type)))
+        .collect();
+
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("entries {} and {}", rest.join(", "), last),
+        _ => format!("entry {}", names.join("")),
+    }
+}
+
+fn describe_merge(group: &[&DcbEntry], connectors: &[Connector], strategy: MergeStrategy) -> String {
+    let entries = describe_entries(group);
+    let first = group[0];
+    let physical = || match connector_of(first, connectors) {
+        Some(conn) => format!("{}, {} at location {}", conn.index, connector_type_to_string(conn.conn_type),
+            conn.location()),
+        None => "none".to_owned(),
+    };
+
+    match (strategy, group.len() > 1) {
+        (MergeStrategy::Bus, true) => format!("{} share bus {}", entries, first.bus),
+        (MergeStrategy::Bus, false) => format!("{} is the only entry on bus {}", entries, first.bus),
+        (MergeStrategy::Connector, true) => format!("{} share connector {}", entries, first.con),
+        (MergeStrategy::Connector, false) => format!("{} is the only entry on connector {}", entries, first.con),
+        (MergeStrategy::ConnectorTable, true) => format!("{} share connector table entry {}", entries, physical()),
+        (MergeStrategy::ConnectorTable, false) => match merge_key(first, connectors, strategy) {
+            Some(_) => format!("{} is the only entry on connector table entry {}", entries, physical()),
+            None => format!("{} has no connector table entry, so it can't be merged", entries),
+        },
+        (MergeStrategy::None, _) => format!("{} is not merged, merging is turned off", entries),
     }
 }
 
 // Merge DVI entries together and condense into info we just need for the user
-fn merge_dcb_entries(parsed_dcb_entries: &mut Vec<DcbEntry>, filtered_pub_entries: &mut Vec<util::Display>) {
+fn merge_dcb_entries(parsed_dcb_entries: &[DcbEntry], connectors: &[Connector], strategy: MergeStrategy,
+                     filtered_pub_entries: &mut Vec<util::Display>) {
     let mut merged_entries: Vec<u8> = Vec::new();
     for dcb_entry in parsed_dcb_entries.iter() {
         if merged_entries.contains(&dcb_entry.entry) {
@@ -164,46 +657,70 @@ fn merge_dcb_entries(parsed_dcb_entries: &mut Vec<DcbEntry>, filtered_pub_entrie
         }
 
         // https://nvidia.github.io/open-gpu-doc/DCB/DCB-4.x-Specification.html#_dcb_device_entries
-        // Use Bus id, not connector index, to merge devices together
-        let dcb_entry_2 = parsed_dcb_entries.iter().find(
-            |x| (x.bus == dcb_entry.bus) && (x.entry != dcb_entry.entry));
+        // Every entry sharing the key is part of the same display
+        let key = merge_key(dcb_entry, connectors, strategy);
+        let group: Vec<&DcbEntry> = parsed_dcb_entries.iter()
+            .filter(|x| x.entry == dcb_entry.entry || (key.is_some() && merge_key(x, connectors, strategy) == key))
+            .collect();
 
-        match dcb_entry_2 {
-            Some(x) => {
-                merged_entries.push(x.entry);
+        merged_entries.extend(group.iter().map(|x| x.entry));
 
-                let mut disp_type = util::DisplayType::DVI;
-                if dcb_entry.entry_type == x.entry_type {
-                    disp_type = get_display_type(x)
-                }
+        let (disp_type, type_reason) = get_display_type(&group, connectors);
 
-                let display = util::Display {
-                    disp_type: disp_type,
-                    dcb_entries: vec![dcb_entry.entry, x.entry],
-                    head_bitmask: dcb_entry.head_bitmask & x.head_bitmask,
-                };
+        let mut merge_reason = describe_merge(&group, connectors, strategy);
+        let head_bitmask = group.iter().fold(0xf, |mask, x| mask & x.head_bitmask);
+        if group.iter().any(|x| x.head_bitmask != head_bitmask) {
+            merge_reason += ", only heads supported by every entry are kept";
+        }
 
-                filtered_pub_entries.push(display);
-            }
-            None => {
-                let display = util::Display {
-                    disp_type: get_display_type(dcb_entry),
-                    dcb_entries: vec![dcb_entry.entry],
-                    head_bitmask: dcb_entry.head_bitmask,
-                };
-
-                filtered_pub_entries.push(display);
-            }
+        let display = util::Display {
+            disp_type,
+            dcb_entries: group.iter().map(|x| x.entry).collect(),
+            head_bitmask,
+            merge_reason,
+            type_reason,
+            edid: None,
+        };
+
+        filtered_pub_entries.push(display);
+    }
+}
+
+fn parse_connector_table(rom: &[u8], dcb: &DcbHeader) -> Vec<Connector> {
+    let mut connectors: Vec<Connector> = vec![];
+    let table = match dcb.connector_table {
+        Some(table) if table + CONN_TABLE_ENTRY_SIZE_OFFSET < rom.len() => table,
+        _ => return connectors,
+    };
+
+    let header_size = rom[table + CONN_TABLE_SIZE_OFFSET] as usize;
+    let count = rom[table + CONN_TABLE_ENTRY_COUNT_OFFSET] as usize;
+    let entry_size = rom[table + CONN_TABLE_ENTRY_SIZE_OFFSET] as usize;
+
+    for index in 0..count {
+        let offset = table + header_size + index * entry_size;
+        if entry_size == 0 || offset + entry_size.min(4) > rom.len() {
+            break;
         }
+
+        // Entries can be shorter than 4 bytes, only the type is always there
+        let raw = rom[offset..offset + entry_size.min(4)].iter().rev()
+            .fold(0u32, |raw, &byte| (raw << 8) | byte as u32);
+
+        connectors.push(Connector { index: index as u8, conn_type: rom[offset], raw });
     }
+
+    println!("Found {} connector table entries", connectors.len());
+    connectors
 }
 
 // Convert indexes of displays to DCB head mask
-fn create_head_mask(display_indexes: &Vec<usize>, displays: &Vec<util::Display>) -> u16 {
+fn create_head_mask(display_indexes: &[usize], displays: &[util::Display]) -> u16 {
     let mut mask: u16 = 0;
     for &idx in display_indexes {
         let dcb_entries = &displays[idx].dcb_entries;
-        for dcb_entry in dcb_entries {
+        // Only the first 16 DCB entries fit in a head mask
+        for &dcb_entry in dcb_entries.iter().filter(|&&entry| entry < 16) {
             mask |= 1 << dcb_entry;
         }
     }
@@ -211,50 +728,143 @@ fn create_head_mask(display_indexes: &Vec<usize>, displays: &Vec<util::Display>)
     mask
 }
 
-pub fn read_rom(filename: &str) -> Result<(Vec<DcbEntry>, Vec<util::Display>), NVErrors> {
-    let rom: Vec<u8>;
-    let mut parsed_dcb_entries: Vec<DcbEntry> = Vec::new();
-    let mut filtered_disp_entries: Vec<util::Display> = Vec::new();
-
-
-    let rom_res = fs::read(filename);
-    match rom_res {
+pub fn read_rom(filename: &str) -> Result<Vbios, NVErrors> {
+    let rom = match fs::read(filename) {
         Ok(bytes) => {
             println!("Read ROM file {}, which is {} bytes long", filename, bytes.len());
-            rom = bytes;    
+            bytes
         }
         Err(e) => {
             println!("{}", "Rom file not found!".red());
             println!("{}", e);
             return Err(NVErrors::FileNotFound);
         }
+    };
+
+    parse_rom(&rom)
+}
+
+// Everything after reading the file, so ROM images can come from anywhere
+pub fn parse_rom(rom: &[u8]) -> Result<Vbios, NVErrors> {
+    let mut parsed_dcb_entries: Vec<DcbEntry> = Vec::new();
+    let mut filtered_disp_entries: Vec<util::Display> = Vec::new();
+
+    let pci = parse_pci_header(rom);
+    match &pci {
+        Some(header) => println!("PCI device {:04x}:{:04x}", header.vendor_id, header.device_id),
+        None => println!("{}", "No PCI header found - unable to identify GPU".yellow()),
+    }
+
+    let bit = bit::parse_bit(rom);
+    if let Some(bit) = &bit {
+        println!("BIT is at {:#x} with {} entries", bit.offset, bit.entries.len());
+    }
+
+    if rom.len() < DCB_HEADER_ADDR + 2 {
+        println!("{}", "ROM is too small to be a VBIOS".red());
+        return Err(NVErrors::Corrupted);
+    }
+
+    let bmp = bmp::parse_bmp(rom);
+    if let Some(bmp) = &bmp {
+        println!("BMP version {}.{:x} is at {:#x}, BIOS version {}", bmp.version_major, bmp.version_minor,
+            bmp.offset, bmp.bios_version_string());
+    }
+
+    let lvds = lvds::parse_lvds(rom, bmp.as_ref(), bit.as_ref());
+    if let Some(lvds) = &lvds {
+        println!("LVDS table is at {:#x} with {} panel straps", lvds.offset, lvds.straps.len());
     }
 
     // DCB 3.0 and 4.0 are very similar, just treat them the same
-    let dcb_header_offset: usize = util::read_uint_16_le(&rom, DCB_HEADER_ADDR) as usize;
-    let dcb_version: u8 = rom[dcb_header_offset];
-    let ver_maj: u8 = dcb_version >> 4;
-    let ver_min: u8 = dcb_version & 0xf;
-    let dcb_size: usize = rom[dcb_header_offset + DCB_SIZE_OFFSET] as usize;
+    let mut dcb_header_offset: usize = util::read_uint_16_le(rom, DCB_HEADER_ADDR) as usize;
+    if !has_dcb_signature(rom, dcb_header_offset) {
+        if let Some(found) = find_dcb_header(rom) {
+            println!("{} DCB pointer at {:#x} is bad, using the DCB header found at {:#x}", "Warning:".yellow(),
+                DCB_HEADER_ADDR, found);
+            dcb_header_offset = found;
+        }
+    }
+    if dcb_header_offset + DCB_SIGNATURE_OFFSET + 4 > rom.len() {
+        println!("{} {:#x}", "DCB header is past the end of the ROM:".red(), dcb_header_offset);
+        return Err(NVErrors::Corrupted);
+    }
+
+    let dcb = parse_dcb_header(rom, dcb_header_offset);
+    let ver_maj: u8 = dcb.version >> 4;
+    let ver_min: u8 = dcb.version & 0xf;
     
-    println!("DCB header is at {:#x} with length {:#x}", dcb_header_offset, dcb_size);
+    println!("DCB header is at {:#x} with length {:#x}", dcb.offset, dcb.size);
     println!("DCB Version {}.{}", ver_maj, ver_min);
 
-    if !parse_signature(dcb_version, &rom, dcb_header_offset) {
+    if !parse_signature(dcb.version, rom, dcb.offset) {
         println!("{}", "Invalid DCB Signature".red());
         return Err(NVErrors::Corrupted);
     }
 
-    parse_dcb_entries(&rom, dcb_header_offset, dcb_size, &mut parsed_dcb_entries);
-    merge_dcb_entries(&mut parsed_dcb_entries, &mut filtered_disp_entries);
-    Ok((parsed_dcb_entries, filtered_disp_entries))
+    parse_dcb_entries(rom, &dcb, &mut parsed_dcb_entries);
+    let connectors = parse_connector_table(rom, &dcb);
+    merge_dcb_entries(&parsed_dcb_entries, &connectors, MergeStrategy::Bus, &mut filtered_disp_entries);
+    Ok(Vbios {
+        rom: rom.to_vec(),
+        crc32: util::crc32(rom),
+        pci,
+        bit,
+        bmp,
+        lvds,
+        dcb,
+        dcb_entries: parsed_dcb_entries,
+        connectors,
+        merge: MergeStrategy::Bus,
+        displays: filtered_disp_entries,
+    })
 }
 
-pub fn create_nvcap_value (nvcap: &mut NVCAP, displays: &Vec<util::Display>,
-                           head_tv: &Vec<usize>, head_0: &Vec<usize>, head_1: &Vec<usize>) {
+// Convert a DCB head mask back to the displays it covers, along with any bits
+// that don't make up a whole display
+pub fn heads_from_mask(mask: u16, displays: &[util::Display]) -> (Vec<usize>, u16) {
+    let mut head: Vec<usize> = vec![];
+    let mut covered: u16 = 0;
+
+    for i in 0..displays.len() {
+        let display_mask = create_head_mask(&[i], displays);
+        if display_mask != 0 && mask & display_mask == display_mask {
+            head.push(i);
+            covered |= display_mask;
+        }
+    }
+
+    (head, mask & !covered)
+}
+
+pub fn update_head_masks(nvcap: &mut NVCAP, displays: &[util::Display],
+                         head_tv: &[usize], head_0: &[usize], head_1: &[usize]) {
     nvcap.dcb_tv_mask = create_head_mask(head_tv, displays);
     nvcap.dcb_0_mask = create_head_mask(head_0, displays);
     nvcap.dcb_1_mask = create_head_mask(head_1, displays);
+}
+
+// NVCAP bytes grouped in 32 bit words, highlighting any byte that differs from `previous`
+pub fn format_nvcap_hex(bytes: &[u8; NVCAP_SIZE], previous: Option<&[u8; NVCAP_SIZE]>) -> String {
+    let mut out = String::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 && i % 4 == 0 {
+            out += " ";
+        }
+
+        let hex = format!("{:02x}", byte);
+        match previous {
+            Some(previous) if previous[i] != *byte => out += &hex.black().on_yellow().to_string(),
+            _ => out += &hex,
+        }
+    }
+
+    out
+}
+
+pub fn create_nvcap_value (nvcap: &mut NVCAP, displays: &[util::Display], head_tv: &[usize],
+                           head_0: &[usize], head_1: &[usize], device_path: Option<&str>) {
+    update_head_masks(nvcap, displays, head_tv, head_0, head_1);
 
     util::header();
 
@@ -264,13 +874,379 @@ pub fn create_nvcap_value (nvcap: &mut NVCAP, displays: &Vec<util::Display>,
 
     println!("{}: ", "NVCAP".cyan());
 
-    let view = nvcap as *const _ as *const u32;
-    let max_bound = mem::size_of::<NVCAP>() / 4;
-    for i in 0..max_bound as isize {
-        let val: u32 = unsafe { *view.offset(i) };
-        print!("{:08x} ", val.to_be());
-    }
+    print!("{}", format_nvcap_hex(&nvcap.to_bytes(), None));
 
     println!("\n");
+    export::print_exports(&nvcap.to_bytes(), device_path);
+    println!();
     util::press_any_key();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use proptest::prelude::*;
+
+    const FIELD_BITS: [(u32, u32); 11] = [
+        DCB_TYPE_BITS, DCB_EDID_PORT_BITS, DCB_HEAD_BITS, DCB_CON_BITS, DCB_BUS_BITS, DCB_LOC_BITS,
+        DCB_BDR_BITS, DCB_BBDR_BITS, DCB_RESOURCES_BITS, DCB_VIRTUAL_BITS, DCB_RESERVED_BITS,
+    ];
+
+    fn field_values(entry: &DcbEntry) -> [u32; 11] {
+        [entry.entry_type, entry.edid_port, entry.head_bitmask, entry.con, entry.bus, entry.loc,
+         entry.bdr, entry.bbdr, entry.output_resources, entry.entry_is_virtual as u32, entry.reserved]
+    }
+
+    fn arb_entry() -> impl Strategy<Value = DcbEntry> {
+        (0u32..16, 0u32..16, 0u32..16, 0u32..16, 0u32..16, 0u32..4, 0u32..2, 0u32..2, 0u32..16, any::<bool>(), 0u32..8)
+            .prop_map(|(entry_type, edid_port, head_bitmask, con, bus, loc, bdr, bbdr, output_resources,
+                        entry_is_virtual, reserved)| DcbEntry {
+                entry_type, edid_port, head_bitmask, con, bus, loc, bdr, bbdr, output_resources,
+                entry_is_virtual, reserved, entry: 0, offset: 0,
+            })
+    }
+
+    // Every bit has to belong to exactly one field, or encoding loses or duplicates it
+    #[test]
+    fn each_bit_decodes_to_one_field() {
+        for bit in 0..32 {
+            let entry = DcbEntry::decode(1 << bit, 0, 0);
+            let set: Vec<u32> = field_values(&entry).iter().copied().filter(|&value| value != 0).collect();
+            assert_eq!(set.len(), 1, "bit {} decodes into {} fields", bit, set.len());
+        }
+    }
+
+    #[test]
+    fn fields_cover_all_bits_without_overlap() {
+        let mut covered: u32 = 0;
+        for bits in FIELD_BITS.iter() {
+            let mask = put_bits(u32::MAX, *bits);
+            assert_eq!(covered & mask, 0, "field at bit {} overlaps another field", bits.0);
+            covered |= mask;
+        }
+
+        assert_eq!(covered, u32::MAX);
+    }
+
+    #[test]
+    fn truncated_roms_do_not_panic() {
+        let rom = desktop_rom().build();
+        for len in 0..rom.len() {
+            let _ = parse_rom(&rom[..len]);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn garbage_roms_do_not_panic(rom in proptest::collection::vec(any::<u8>(), 0..1024)) {
+            let _ = parse_rom(&rom);
+        }
+
+        #[test]
+        fn decode_then_encode_is_identity(conn in any::<u32>()) {
+            prop_assert_eq!(DcbEntry::decode(conn, 0, 0).encode(), conn);
+        }
+
+        #[test]
+        fn encode_then_decode_is_identity(entry in arb_entry()) {
+            prop_assert_eq!(DcbEntry::decode(entry.encode(), 0, 0), entry);
+        }
+
+        // Changing one field must not leak into the bits of any other field
+        #[test]
+        fn fields_are_independent(conn in any::<u32>(), field in 0usize..11, value in any::<u32>()) {
+            let bits = FIELD_BITS[field];
+            let mask = put_bits(u32::MAX, bits);
+            let patched = (conn & !mask) | put_bits(value, bits);
+
+            let before = field_values(&DcbEntry::decode(conn, 0, 0));
+            let after = field_values(&DcbEntry::decode(patched, 0, 0));
+            for i in 0..before.len() {
+                if i != field {
+                    prop_assert_eq!(before[i], after[i]);
+                }
+            }
+
+            prop_assert_eq!(after[field], get_bits(value, (0, bits.1)));
+        }
+    }
+
+    fn desktop_rom() -> RomBuilder {
+        RomBuilder::new()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(CRT, 2, 3, 2, 2))
+            .entry(dcb_entry(TV, 0xf, 3, 3, 3))
+    }
+
+    #[test]
+    fn parses_desktop_rom() {
+        let rom = desktop_rom().build();
+        let vbios = parse_rom(&rom).unwrap();
+
+        let pci = vbios.pci.as_ref().unwrap();
+        assert_eq!((pci.vendor_id, pci.device_id), (0x10de, 0x0402));
+        assert_eq!(vbios.crc32, util::crc32(&rom));
+
+        let offsets: Vec<usize> = vbios.dcb_entries.iter().map(|entry| entry.offset).collect();
+        assert_eq!(offsets, vec![0x117, 0x11f, 0x127, 0x12f, 0x137]);
+        assert_eq!(vbios.dcb_entries[2].encode(), dcb_entry(TMDS, 1, 3, 1, 1));
+
+        assert_eq!(describe_displays(&vbios.displays), vec![
+            "DVI [0, 1] 0x3",
+            "TMDS [2] 0x3",
+            "Analog [3] 0x3",
+            "TV [4] 0x3",
+        ]);
+    }
+
+    #[test]
+    fn reads_rom_file() {
+        let path = std::env::temp_dir().join(format!("nvcap-test-{}.rom", std::process::id()));
+        let rom = desktop_rom().build();
+        fs::write(&path, &rom).unwrap();
+
+        let vbios = read_rom(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(vbios.unwrap().crc32, util::crc32(&rom));
+        assert!(matches!(read_rom("/nonexistent/nvcap.rom"), Err(NVErrors::FileNotFound)));
+    }
+
+    #[test]
+    fn skip_entries_are_ignored_and_eol_stops_parsing() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+            .skip()
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .eol()
+            .entry(dcb_entry(CRT, 2, 3, 2, 2))
+            .build();
+        let vbios = parse_rom(&rom).unwrap();
+
+        let indexes: Vec<u8> = vbios.dcb_entries.iter().map(|entry| entry.entry).collect();
+        assert_eq!(indexes, vec![0, 2]);
+        assert_eq!(describe_displays(&vbios.displays), vec!["LVDS [0] 0x1", "TMDS [2] 0x3"]);
+    }
+
+    #[test]
+    fn dcb_header_can_be_anywhere() {
+        let rom = desktop_rom().dcb_offset(0x5a0).version(0x30).header_size(0x16).build();
+        let vbios = parse_rom(&rom).unwrap();
+
+        assert_eq!(vbios.dcb_entries[0].offset, 0x5b6);
+        assert_eq!(vbios.displays.len(), 4);
+    }
+
+    fn shared_bus_rom() -> RomBuilder {
+        // DVI-I and HDMI on one bus, ie. a board sharing DDC lines between connectors
+        RomBuilder::new()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 1, 1, 0))
+            .entry(dcb_entry(CRT, 2, 3, 2, 1))
+            .connector(CONN_DVI_I)
+            .connector(CONN_HDMI)
+            .connector(CONN_VGA)
+    }
+
+    fn merged_by(rom: &[u8], strategy: MergeStrategy) -> Vec<String> {
+        let mut vbios = parse_rom(rom).unwrap();
+        vbios.set_merge_strategy(strategy);
+        describe_displays(&vbios.displays)
+    }
+
+    #[test]
+    fn parses_dcb_header() {
+        let mut rom = shared_bus_rom().build();
+        rom[0x104..0x106].copy_from_slice(&0x200u16.to_le_bytes());
+        rom[0x116] = 0x3;
+        let dcb = parse_rom(&rom).unwrap().dcb;
+
+        assert_eq!((dcb.offset, dcb.version, dcb.size, dcb.entry_count, dcb.entry_size), (0x100, 0x40, 0x17, 4, 8));
+        assert_eq!(dcb.i2c_table, Some(0x200));
+        assert_eq!(dcb.gpio_table, None);
+        assert_eq!(dcb.connector_table, Some(0x137));
+        assert_eq!(dcb.flags, Some(0x3));
+
+        // DCB 3.0 headers end before the flags
+        let dcb = parse_rom(&desktop_rom().version(0x30).header_size(0x16).build()).unwrap().dcb;
+        assert_eq!(dcb.flags, None);
+    }
+
+    #[test]
+    fn parses_connector_table() {
+        let vbios = parse_rom(&shared_bus_rom().build()).unwrap();
+        let types: Vec<&str> = vbios.connectors.iter().map(|conn| connector_type_to_string(conn.conn_type)).collect();
+        assert_eq!(types, vec!["DVI-I", "HDMI", "VGA"]);
+        assert_eq!(vbios.connectors[1].raw, 0x0060);
+    }
+
+    #[test]
+    fn merges_every_entry_on_a_bus() {
+        assert_eq!(merged_by(&shared_bus_rom().build(), MergeStrategy::Bus), vec![
+            "DVI [0, 1, 2] 0x1",
+            "Analog [3] 0x3",
+        ]);
+    }
+
+    #[test]
+    fn explains_merges() {
+        let mut vbios = parse_rom(&shared_bus_rom().build()).unwrap();
+        assert_eq!(vbios.displays[0].merge_reason, "entries 0x0 (TMDS), 0x1 (CRT) and 0x2 (TMDS) share bus 0, \
+            only heads supported by every entry are kept");
+        assert_eq!(vbios.displays[0].type_reason, "different output types on one display are shown as DVI");
+        assert_eq!(vbios.displays[1].merge_reason, "entry 0x3 (CRT) is the only entry on bus 1");
+        assert_eq!(vbios.displays[1].type_reason, "connector table entry 2 (VGA) is shown as Analog");
+
+        vbios.set_merge_strategy(MergeStrategy::ConnectorTable);
+        assert_eq!(vbios.displays[0].merge_reason, "entries 0x0 (TMDS) and 0x1 (CRT) share connector table entry 0, DVI-I at location 0");
+    }
+
+    #[test]
+    fn merges_by_connector() {
+        let expected = vec!["DVI [0, 1] 0x3", "HDMI [2] 0x1", "Analog [3] 0x3"];
+        assert_eq!(merged_by(&shared_bus_rom().build(), MergeStrategy::Connector), expected);
+        assert_eq!(merged_by(&shared_bus_rom().build(), MergeStrategy::ConnectorTable), expected);
+    }
+
+    #[test]
+    fn keeps_identical_connectors_apart() {
+        // Two VGA ports without hotplug pins look the same in the connector table
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(CRT, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 1, 3, 1, 0))
+            .connector(CONN_VGA)
+            .connector(CONN_VGA)
+            .build();
+
+        assert_eq!(merged_by(&rom, MergeStrategy::Bus), vec!["Analog [0, 1] 0x3"]);
+        assert_eq!(merged_by(&rom, MergeStrategy::ConnectorTable), vec!["Analog [0] 0x3", "Analog [1] 0x3"]);
+
+        let mut vbios = parse_rom(&rom).unwrap();
+        vbios.set_merge_strategy(MergeStrategy::ConnectorTable);
+        assert_eq!(vbios.displays[1].merge_reason,
+            "entry 0x1 (CRT) is the only entry on connector table entry 1, VGA at location 0");
+        assert_eq!(vbios.displays[1].type_reason, "connector table entry 1 (VGA) is shown as Analog");
+    }
+
+    #[test]
+    fn connector_table_decides_display_type() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(DP, 0, 1, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(TMDS, 2, 3, 2, 2))
+            .entry(dcb_entry(DP, 3, 3, 3, 3))
+            .entry(dcb_entry(TMDS, 3, 3, 3, 3))
+            .connector(CONN_EDP)
+            .connector(CONN_HDMI)
+            .connector(CONN_DVI_D)
+            .connector(CONN_DP)
+            .build();
+        let vbios = parse_rom(&rom).unwrap();
+
+        assert_eq!(describe_displays(&vbios.displays), vec![
+            "EDP [0] 0x1",
+            "HDMI [1] 0x3",
+            "TMDS [2] 0x3",
+            "DisplayPort [3, 4] 0x3",
+        ]);
+        assert_eq!(vbios.displays[1].type_reason, "connector table entry 1 (HDMI) is shown as HDMI");
+        assert!(util::is_mobile(&vbios.displays));
+    }
+
+    #[test]
+    fn output_types_decide_display_type_without_a_connector_table() {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(DP, 0, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(SDI, 1, 3, 1, 1))
+            .entry(dcb_entry(7, 2, 3, 2, 2))
+            .build();
+        let vbios = parse_rom(&rom).unwrap();
+
+        assert_eq!(describe_displays(&vbios.displays), vec![
+            "DisplayPort [0, 1] 0x3",
+            "SDI [2] 0x3",
+            "Unknown(7) [3] 0x3",
+        ]);
+        assert_eq!(vbios.displays[0].type_reason, "DisplayPort with other outputs is shown as DisplayPort (dual mode)");
+        assert_eq!(vbios.displays[1].type_reason, "SDI outputs are shown as SDI");
+    }
+
+    #[test]
+    fn connector_table_strategy_needs_a_connector_table() {
+        let rom = desktop_rom().build();
+        assert_eq!(merged_by(&rom, MergeStrategy::ConnectorTable), merged_by(&rom, MergeStrategy::None));
+    }
+
+    #[test]
+    fn no_merging() {
+        assert_eq!(merged_by(&desktop_rom().build(), MergeStrategy::None), vec![
+            "TMDS [0] 0x3",
+            "Analog [1] 0x3",
+            "TMDS [2] 0x3",
+            "Analog [3] 0x3",
+            "TV [4] 0x3",
+        ]);
+    }
+
+    #[test]
+    fn finds_dcb_without_a_good_pointer() {
+        let mut rom = desktop_rom().dcb_offset(0x180).build();
+        rom[0x36..0x38].copy_from_slice(&0x120u16.to_le_bytes());
+        let vbios = parse_rom(&rom).unwrap();
+
+        assert_eq!(vbios.dcb.offset, 0x180);
+        assert_eq!(vbios.displays.len(), 4);
+
+        rom[0x36..0x38].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert_eq!(parse_rom(&rom).unwrap().dcb.offset, 0x180);
+    }
+
+    #[test]
+    fn old_roms_have_a_bmp() {
+        let vbios = parse_rom(&desktop_rom().bmp(5, 0x25).build()).unwrap();
+        assert_eq!(vbios.bmp.unwrap().bios_version_string(), "05.72.22.24");
+        assert!(vbios.bit.is_none());
+    }
+
+    #[test]
+    fn rom_without_pci_header_still_parses() {
+        let vbios = parse_rom(&desktop_rom().no_pci().build()).unwrap();
+        assert!(vbios.pci.is_none());
+        assert_eq!(vbios.displays.len(), 4);
+    }
+
+    #[test]
+    fn rejects_bad_signature_and_versions() {
+        assert!(parse_rom(&desktop_rom().signature(0x12345678).build()).is_err());
+        assert!(parse_rom(&desktop_rom().version(0x22).build()).is_err());
+        assert!(parse_rom(&desktop_rom().version(0x50).build()).is_err());
+    }
+
+    #[test]
+    fn patched_rom_keeps_a_valid_checksum() {
+        let mut rom = desktop_rom().build();
+        let mut entries = parse_rom(&rom).unwrap().dcb_entries;
+        entries[3].entry_type = DCB_TYPE_SKIP;
+
+        write_dcb_entries(&mut rom, &entries);
+        fix_checksum(&mut rom).unwrap();
+
+        assert_eq!(rom.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)), 0);
+        assert_eq!(describe_displays(&parse_rom(&rom).unwrap().displays), vec![
+            "DVI [0, 1] 0x3",
+            "TMDS [2] 0x3",
+            "TV [4] 0x3",
+        ]);
+    }
+
+    #[test]
+    fn nvcap_bytes_round_trip() {
+        let bytes: [u8; NVCAP_SIZE] = [5, 1, 1, 0, 0x10, 0, 0x1, 0, 0xe, 0, 0, 0, 0, 0, 0, 0xb, 0, 0, 0, 0];
+        assert_eq!(NVCAP::from_bytes(&bytes).to_bytes(), bytes);
+    }
+}
@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::Path;
+use colored::*;
+use crate::nvidia::{self, DcbEntry, Vbios};
+use crate::util::{self, NVErrors};
+
+const FIELD_COUNT: usize = 10;
+
+// Name and largest value of each editable field, in the order they are listed
+const FIELDS: [(&str, u32); FIELD_COUNT] = [
+    ("Type", 0xf),
+    ("EdidPort", 0xf),
+    ("Head", 0xf),
+    ("Connector", 0xf),
+    ("Bus", 0xf),
+    ("Loc", 0x3),
+    ("BDR", 0x1),
+    ("BBDR", 0x1),
+    ("Resources", 0xf),
+    ("Virtual", 0x1),
+];
+
+fn field_value(entry: &DcbEntry, idx: usize) -> u32 {
+    match idx {
+        0 => entry.entry_type,
+        1 => entry.edid_port,
+        2 => entry.head_bitmask,
+        3 => entry.con,
+        4 => entry.bus,
+        5 => entry.loc,
+        6 => entry.bdr,
+        7 => entry.bbdr,
+        8 => entry.output_resources,
+        _ => entry.entry_is_virtual as u32,
+    }
+}
+
+fn set_field(entry: &mut DcbEntry, idx: usize, value: u32) {
+    match idx {
+        0 => entry.entry_type = value,
+        1 => entry.edid_port = value,
+        2 => entry.head_bitmask = value,
+        3 => entry.con = value,
+        4 => entry.bus = value,
+        5 => entry.loc = value,
+        6 => entry.bdr = value,
+        7 => entry.bbdr = value,
+        8 => entry.output_resources = value,
+        _ => entry.entry_is_virtual = value == 1,
+    }
+}
+
+fn patched_filename(filename: &str) -> String {
+    match filename.strip_suffix(".rom") {
+        Some(stem) => format!("{}.patched.rom", stem),
+        None => format!("{}.patched.rom", filename),
+    }
+}
+
+fn list_entries(original: &[DcbEntry], entries: &[DcbEntry]) {
+    for (entry, before) in entries.iter().zip(original.iter()) {
+        let changed = if entry != before { "*".yellow().to_string() } else { " ".to_owned() };
+        println!("{}{} {:#010x}  Type: {} Head: {} Connector: {} Bus: {}", changed,
+            format!("({:x})", entry.entry).bright_blue(), entry.encode(),
+            nvidia::dcb_type_to_string(entry.entry_type), entry.head_bitmask, entry.con, entry.bus);
+    }
+}
+
+fn edit_entry(original: &DcbEntry, entry: &mut DcbEntry) {
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("{} {:#x} at {:#x}: {:#010x} (was {:#010x})", "DCB Entry".bright_blue(),
+            entry.entry, entry.offset, entry.encode(), original.encode());
+        println!();
+
+        for (i, (name, _)) in FIELDS.iter().enumerate() {
+            let value = field_value(entry, i);
+            let line = format!("{} {}: {:#x}", format!("({})", i + 1).bright_blue(), name, value);
+            if value != field_value(original, i) {
+                println!("{} {}", line, format!("(was {:#x})", field_value(original, i)).yellow());
+            } else {
+                println!("{}", line);
+            }
+        }
+
+        println!();
+        println!("{} Disable this entry (sets Type to {:#x}, skipped by the driver)",
+            "(d)".bright_blue(), nvidia::DCB_TYPE_SKIP);
+        println!("{} Undo changes to this entry", "(u)".bright_blue());
+        println!("{} Return to previous menu", "(q)".bright_blue());
+
+        util::prompt("Enter \"<field> <value>\" (value in hex, ie. \"3 1\"), or an option: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        match input.as_str() {
+            "q" => break,
+            "d" => { entry.entry_type = nvidia::DCB_TYPE_SKIP; continue; }
+            "u" => { *entry = original.clone(); continue; }
+            _ => {}
+        }
+
+        let args: Vec<&str> = input.split_whitespace().collect();
+        if args.len() != 2 {
+            continue;
+        }
+
+        let idx = match args[0].parse::<usize>() {
+            Ok(idx) if (1..=FIELD_COUNT).contains(&idx) => idx - 1,
+            _ => continue,
+        };
+
+        match u32::from_str_radix(args[1].trim_start_matches("0x"), 16) {
+            Ok(value) if value <= FIELDS[idx].1 => set_field(entry, idx, value),
+            _ => {
+                println!("{} {} must be between 0 and {:#x}", "Invalid value:".red(), FIELDS[idx].0, FIELDS[idx].1);
+                util::press_any_key();
+            }
+        }
+    }
+}
+
+// Copy the ROM with the edited entries written back in, fixing up the image checksum
+fn write_patched_rom(vbios: &Vbios, filename: &str, entries: &[DcbEntry]) -> Result<String, NVErrors> {
+    let mut rom = match fs::read(filename) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("{}", "Rom file not found!".red());
+            println!("{}", e);
+            return Err(NVErrors::FileNotFound);
+        }
+    };
+
+    // Entry offsets are only good for the ROM they were read from
+    if util::crc32(&rom) != vbios.crc32 {
+        println!("{}", "The ROM changed since it was opened, reopen it before patching".red());
+        return Err(NVErrors::Corrupted);
+    }
+
+    nvidia::write_dcb_entries(&mut rom, entries);
+    match nvidia::fix_checksum(&mut rom) {
+        Some(checksum) => println!("Image checksum byte set to {:#04x}", checksum),
+        None => println!("{} Unable to find the ROM image size, checksum was not fixed", "Warning:".yellow()),
+    }
+
+    let path = patched_filename(filename);
+    match fs::write(&path, &rom) {
+        Ok(_) => Ok(path),
+        Err(e) => {
+            println!("{}", "Unable to write patched ROM!".red());
+            println!("{}", e);
+            Err(NVErrors::FileNotFound)
+        }
+    }
+}
+
+pub fn patch_dcb(vbios: &Vbios, filename: &str) {
+    let mut entries: Vec<DcbEntry> = vbios.dcb_entries.clone();
+
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        println!("DCB Entries (changed entries are marked with {}):", "*".yellow());
+        list_entries(&vbios.dcb_entries, &entries);
+        println!();
+        println!("{} Write patched copy of the ROM to {}", "(w)".bright_blue(), patched_filename(filename).green());
+        println!("{} Return to previous menu (changes are lost)", "(q)".bright_blue());
+
+        util::prompt("Select a DCB entry to edit, or an option: ", &mut input);
+        input = input.trim().to_lowercase();
+
+        if input.eq("q") {
+            break;
+        }
+
+        if input.eq("w") {
+            let path = patched_filename(filename);
+            if Path::new(&path).exists() {
+                input.clear();
+                util::prompt(&format!("{} already exists, overwrite it? (y/N): ", path), &mut input);
+                if !input.trim().to_lowercase().starts_with('y') {
+                    continue;
+                }
+            }
+
+            match write_patched_rom(vbios, filename, &entries) {
+                Ok(path) => println!("Patched ROM written to {}", path.green()),
+                Err(_) => println!("{}", "Patched ROM was not written".red()),
+            }
+
+            util::press_any_key();
+            continue;
+        }
+
+        let entry_idx = match u8::from_str_radix(input.trim_start_matches("0x"), 16) {
+            Ok(entry_idx) => entry_idx,
+            Err(_) => continue,
+        };
+
+        if let Some(pos) = entries.iter().position(|entry| entry.entry == entry_idx) {
+            edit_entry(&vbios.dcb_entries[pos], &mut entries[pos]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use crate::test_support::*;
+
+    fn board() -> RomBuilder {
+        RomBuilder::new()
+            .entry(dcb_entry(TMDS, 0, 3, 0, 0))
+            .entry(dcb_entry(CRT, 1, 3, 1, 1))
+            .entry(dcb_entry(TV, 2, 3, 2, 2))
+    }
+
+    // Patches `rom` from a scratch directory, returns the patched copy
+    fn patch(name: &str, rom: &[u8], edit: impl Fn(&mut Vec<DcbEntry>)) -> Vec<u8> {
+        let dir = env::temp_dir().join(format!("nvcap_patcher_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("board.rom").to_string_lossy().to_string();
+        fs::write(&filename, rom).unwrap();
+
+        let vbios = nvidia::parse_rom(rom).unwrap();
+        let mut entries = vbios.dcb_entries.clone();
+        edit(&mut entries);
+        let path = write_patched_rom(&vbios, &filename, &entries).unwrap();
+        assert_eq!(path, dir.join("board.patched.rom").to_string_lossy());
+
+        let patched = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        patched
+    }
+
+    fn image_sum(rom: &[u8]) -> u8 {
+        let size = rom[2] as usize * 512;
+        assert!(size > 0 && size <= rom.len());
+        rom[..size].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+    }
+
+    #[test]
+    fn disables_an_entry() {
+        let patched = patch("disable", &board().build(), |entries| entries[1].entry_type = nvidia::DCB_TYPE_SKIP);
+
+        let vbios = nvidia::parse_rom(&patched).unwrap();
+        assert_eq!(describe_displays(&vbios.displays), ["TMDS [0] 0x3", "TV [2] 0x3"]);
+        assert_eq!(image_sum(&patched), 0);
+    }
+
+    #[test]
+    fn edits_a_field() {
+        // Head is the third field
+        let patched = patch("edit", &board().build(), |entries| set_field(&mut entries[0], 2, 0x1));
+
+        let vbios = nvidia::parse_rom(&patched).unwrap();
+        assert_eq!(field_value(&vbios.dcb_entries[0], 2), 0x1);
+        assert_eq!(describe_displays(&vbios.displays), ["TMDS [0] 0x1", "Analog [1] 0x3", "TV [2] 0x3"]);
+        assert_eq!(image_sum(&patched), 0);
+    }
+
+    #[test]
+    fn only_fixes_the_first_image_checksum() {
+        // ie. an EFI image after the legacy one, which has its own checksum
+        let mut rom = board().build();
+        let first_image = rom.len();
+        rom.extend([0x55, 0xaa, 0x01]);
+        rom.extend([0x5a; 509]);
+
+        let patched = patch("images", &rom, |entries| set_field(&mut entries[2], 4, 0x3));
+        assert_eq!(image_sum(&patched), 0);
+        assert_ne!(patched[first_image - 1], rom[first_image - 1]);
+        assert_eq!(patched[first_image..], rom[first_image..]);
+    }
+}
@@ -0,0 +1,31 @@
+// The README example, checked from the DCB all the way to the NVCAP. The DCB region in
+// tests/dcb is rebuilt from the displays in the README screenshot, not dumped from a ROM.
+use crate::test_support::*;
+use crate::{gpus, new_session, nvidia};
+
+#[test]
+fn readme_8800m_gtx_example() {
+    let dcb = parse_hex_fixture(include_str!("../tests/dcb/8800m_gtx.txt"));
+    let rom = RomBuilder::new().pci(0x10de, 0x060c).raw_dcb(&dcb).build();
+    let vbios = nvidia::parse_rom(&rom).unwrap();
+    let gpu = gpus::lookup(0x10de, 0x060c);
+
+    // (1) LVDS, (2) Digital, (3) Digital, (4) Analog and (5) TV, all on heads 1 and 2
+    assert_eq!(describe_displays(&vbios.displays),
+        ["LVDS [0] 0x3", "TMDS [1] 0x3", "TMDS [2] 0x3", "Analog [3] 0x3", "TV [4] 0x3"]);
+
+    // TV [5], head 1 [1] and head 2 [2, 3, 4]
+    let mut state = new_session(&vbios, "test.rom", &gpus::suggest_version(gpu), &gpus::suggest_field_f(gpu));
+    assert_eq!(state.head_tv, [4], "TV head");
+    assert_eq!(state.head_0, [0], "head 1");
+    assert_eq!(state.head_1, [1, 2, 3], "head 2");
+    assert_eq!(state.nvcap.version, 5);
+
+    // The rest of the NVCAP fields as set in the screenshot
+    state.nvcap.is_composite = false;
+    state.nvcap.script_based_power_and_backlight = false;
+    state.nvcap.field_f = 0xf;
+    nvidia::update_head_masks(&mut state.nvcap, &vbios.displays, &state.head_tv, &state.head_0, &state.head_1);
+    let nvcap: String = state.nvcap.to_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    assert_eq!(nvcap, "05010000100001000e0000000000000f00000000");
+}
@@ -0,0 +1,611 @@
+use colored::*;
+use crate::nvidia::{self, DcbEntry, Vbios, DCB_CONN_DP, DCB_CONN_LVDS, DCB_CONN_TMDS};
+use crate::util;
+
+// Init scripts are the byte code the VBIOS (and nouveau, in nvkm/subdev/bios/init.c) runs to
+// bring up the card and its outputs. Opcode lengths follow nouveau; nothing is executed, so
+// conditions are listed but not evaluated.
+
+// Stop runaway scripts in garbage data
+const MAX_INSTRUCTIONS: usize = 1024;
+const MAX_TABLE_SCRIPTS: usize = 64;
+
+// BIT 'I' has the init script table pointer first
+const BIT_INIT_ID: u8 = b'I';
+const BIT_INIT_SCRIPT_TABLE_OFFSET: usize = 0;
+
+// BIT 'U' version 1 points at the display script table, see nvbios_disp_table/nvbios_outp_parse
+const BIT_DISPLAY_SCRIPTS_ID: u8 = b'U';
+const DISP_TABLE_VERSIONS: [u8; 3] = [0x20, 0x21, 0x22];
+const DISP_HEADER_SIZE_OFFSET: usize = 0x1;
+const DISP_ENTRY_SIZE_OFFSET: usize  = 0x2;
+const DISP_COUNT_OFFSET: usize       = 0x3;
+const DISP_SUB_SIZE_OFFSET: usize    = 0x4;
+const OUTP_TYPE_OFFSET: usize    = 0x0;
+const OUTP_MASK_OFFSET: usize    = 0x2;
+const OUTP_SCRIPTS_OFFSET: usize = 0x6;
+// Link bits in the second DCB dword, used for TMDS, LVDS and DP
+const DCB_LINK_SHIFT: u32 = 4;
+
+const INIT_DONE: u8 = 0x71;
+const INIT_JUMP: u8 = 0x5c;
+
+#[derive(PartialEq)]
+enum Flow {
+    Next,
+    Done,
+    Jump,
+    // Length comes from the memory strap tables, which aren't parsed
+    Unknown,
+}
+
+struct Opcode {
+    code: u8,
+    name: &'static str,
+    // Name and size in bytes of each operand after the opcode byte
+    operands: &'static [(&'static str, usize)],
+    // Operand holding the number of trailing items, and the size of each item
+    items: Option<(usize, usize)>,
+    flow: Flow,
+}
+
+const fn op(code: u8, name: &'static str, operands: &'static [(&'static str, usize)]) -> Opcode {
+    Opcode { code, name, operands, items: None, flow: Flow::Next }
+}
+
+const fn op_items(code: u8, name: &'static str, operands: &'static [(&'static str, usize)], count: usize, size: usize) -> Opcode {
+    Opcode { code, name, operands, items: Some((count, size)), flow: Flow::Next }
+}
+
+const fn op_flow(code: u8, name: &'static str, operands: &'static [(&'static str, usize)], flow: Flow) -> Opcode {
+    Opcode { code, name, operands, items: None, flow }
+}
+
+const OPCODES: &[Opcode] = &[
+    op_items(0x32, "INIT_IO_RESTRICT_PROG", &[("port", 2), ("index", 1), ("mask", 1), ("shift", 1), ("count", 1), ("reg", 4)], 4, 4),
+    op(0x33, "INIT_REPEAT", &[("count", 1)]),
+    op_items(0x34, "INIT_IO_RESTRICT_PLL", &[("port", 2), ("index", 1), ("mask", 1), ("shift", 1), ("io_flag", 1), ("count", 1), ("reg", 4)], 6, 2),
+    op(0x36, "INIT_END_REPEAT", &[]),
+    op(0x37, "INIT_COPY", &[("reg", 4), ("shift", 1), ("smask", 1), ("port", 2), ("index", 1), ("mask", 1)]),
+    op(0x38, "INIT_NOT", &[]),
+    op(0x39, "INIT_IO_FLAG_CONDITION", &[("cond", 1)]),
+    op(0x3a, "INIT_GENERIC_CONDITION", &[("cond", 1), ("size", 1)]),
+    op(0x3b, "INIT_IO_MASK_OR", &[("index", 1)]),
+    op(0x3c, "INIT_IO_OR", &[("index", 1)]),
+    op(0x47, "INIT_ANDN_REG", &[("reg", 4), ("mask", 4)]),
+    op(0x48, "INIT_OR_REG", &[("reg", 4), ("mask", 4)]),
+    op_items(0x49, "INIT_INDEX_ADDRESS_LATCHED", &[("reg", 4), ("dreg", 4), ("mask", 4), ("data", 4), ("count", 1)], 4, 2),
+    op_items(0x4a, "INIT_IO_RESTRICT_PLL2", &[("port", 2), ("index", 1), ("mask", 1), ("shift", 1), ("count", 1), ("reg", 4)], 4, 4),
+    op(0x4b, "INIT_PLL2", &[("reg", 4), ("freq", 4)]),
+    op_items(0x4c, "INIT_I2C_BYTE", &[("index", 1), ("addr", 1), ("count", 1)], 2, 3),
+    op_items(0x4d, "INIT_ZM_I2C_BYTE", &[("index", 1), ("addr", 1), ("count", 1)], 2, 2),
+    op_items(0x4e, "INIT_ZM_I2C", &[("index", 1), ("addr", 1), ("count", 1)], 2, 1),
+    op(0x4f, "INIT_TMDS", &[("tmds", 1), ("addr", 1), ("mask", 1), ("data", 1)]),
+    op_items(0x50, "INIT_ZM_TMDS_GROUP", &[("tmds", 1), ("count", 1)], 1, 2),
+    op_items(0x51, "INIT_CR_INDEX_ADDRESS_LATCHED", &[("addr0", 1), ("addr1", 1), ("base", 1), ("count", 1)], 3, 1),
+    op(0x52, "INIT_CR", &[("addr", 1), ("mask", 1), ("data", 1)]),
+    op(0x53, "INIT_ZM_CR", &[("addr", 1), ("data", 1)]),
+    op_items(0x54, "INIT_ZM_CR_GROUP", &[("count", 1)], 0, 2),
+    op(0x56, "INIT_CONDITION_TIME", &[("cond", 1), ("retry", 1)]),
+    op(0x57, "INIT_LTIME", &[("msec", 2)]),
+    op_items(0x58, "INIT_ZM_REG_SEQUENCE", &[("base", 4), ("count", 1)], 1, 4),
+    op(0x59, "INIT_PLL_INDIRECT", &[("reg", 4), ("addr", 2)]),
+    op(0x5a, "INIT_ZM_REG_INDIRECT", &[("reg", 4), ("addr", 2)]),
+    op(0x5b, "INIT_SUB_DIRECT", &[("addr", 2)]),
+    op_flow(INIT_JUMP, "INIT_JUMP", &[("addr", 2)], Flow::Jump),
+    op(0x5e, "INIT_I2C_IF", &[("index", 1), ("addr", 1), ("reg", 1), ("mask", 1), ("data", 1)]),
+    op(0x5f, "INIT_COPY_NV_REG", &[("sreg", 4), ("shift", 1), ("smask", 4), ("sxor", 4), ("dreg", 4), ("dmask", 4)]),
+    op(0x62, "INIT_ZM_INDEX_IO", &[("port", 2), ("index", 1), ("data", 1)]),
+    op(0x63, "INIT_COMPUTE_MEM", &[]),
+    op(0x65, "INIT_RESET", &[("reg", 4), ("data1", 4), ("data2", 4)]),
+    op(0x66, "INIT_CONFIGURE_MEM", &[]),
+    op(0x67, "INIT_CONFIGURE_CLK", &[]),
+    op(0x68, "INIT_CONFIGURE_PREINIT", &[]),
+    op(0x69, "INIT_IO", &[("port", 2), ("mask", 1), ("data", 1)]),
+    op(0x6b, "INIT_SUB", &[("index", 1)]),
+    op(0x6d, "INIT_RAM_CONDITION", &[("mask", 1), ("value", 1)]),
+    op(0x6e, "INIT_NV_REG", &[("reg", 4), ("mask", 4), ("data", 4)]),
+    op(0x6f, "INIT_MACRO", &[("macro", 1)]),
+    op_flow(INIT_DONE, "INIT_DONE", &[], Flow::Done),
+    op(0x72, "INIT_RESUME", &[]),
+    op(0x73, "INIT_STRAP_CONDITION", &[("mask", 4), ("value", 4)]),
+    op(0x74, "INIT_TIME", &[("usec", 2)]),
+    op(0x75, "INIT_CONDITION", &[("cond", 1)]),
+    op(0x76, "INIT_IO_CONDITION", &[("cond", 1)]),
+    op(0x77, "INIT_ZM_REG16", &[("reg", 4), ("data", 2)]),
+    op(0x78, "INIT_INDEX_IO", &[("port", 2), ("index", 1), ("mask", 1), ("data", 1)]),
+    op(0x79, "INIT_PLL", &[("reg", 4), ("freq", 2)]),
+    op(0x7a, "INIT_ZM_REG", &[("reg", 4), ("data", 4)]),
+    op_flow(0x87, "INIT_RAM_RESTRICT_PLL", &[("type", 1)], Flow::Unknown),
+    op(0x8c, "INIT_RESERVED", &[]),
+    op(0x8d, "INIT_RESERVED", &[]),
+    op(0x8e, "INIT_GPIO", &[]),
+    op_flow(0x8f, "INIT_RAM_RESTRICT_ZM_REG_GROUP", &[("reg", 4), ("regincr", 1), ("num", 1)], Flow::Unknown),
+    op(0x90, "INIT_COPY_ZM_REG", &[("sreg", 4), ("dreg", 4)]),
+    op_items(0x91, "INIT_ZM_REG_GROUP", &[("addr", 4), ("count", 1)], 1, 4),
+    op(0x92, "INIT_RESERVED", &[]),
+    op(0x96, "INIT_XLAT", &[("sreg", 4), ("sshift", 1), ("smask", 1), ("index", 1), ("dreg", 4), ("dmask", 4), ("shift", 1)]),
+    op(0x97, "INIT_ZM_MASK_ADD", &[("addr", 4), ("mask", 4), ("add", 4)]),
+    op_items(0x98, "INIT_AUXCH", &[("addr", 4), ("count", 1)], 1, 2),
+    op_items(0x99, "INIT_ZM_AUXCH", &[("addr", 4), ("count", 1)], 1, 1),
+    op(0x9a, "INIT_I2C_LONG_IF", &[("index", 1), ("addr", 1), ("reglo", 1), ("reghi", 1), ("mask", 1), ("data", 1)]),
+    op_items(0xa9, "INIT_GPIO_NE", &[("count", 1)], 0, 1),
+    op(0xaa, "INIT_RESERVED", &[("unused", 3)]),
+];
+
+// Opcodes that talk to GPIOs, which is how most laptops switch panel power and backlight
+const GPIO_OPCODES: [u8; 2] = [0x8e, 0xa9];
+
+fn find_opcode(code: u8) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|opcode| opcode.code == code)
+}
+
+pub struct Instruction {
+    pub offset: usize,
+    pub code: u8,
+    pub operands: Vec<u32>,
+    // Trailing items, ie. the values written by INIT_ZM_REG_SEQUENCE
+    pub items: Vec<u32>,
+    pub len: usize,
+}
+
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        find_opcode(self.code).map_or("UNKNOWN", |opcode| opcode.name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ScriptEnd {
+    Done,
+    Jump(usize),
+    UnknownOpcode(u8),
+    UnknownLength(u8),
+    Truncated,
+    TooLong,
+}
+
+pub struct Script {
+    pub label: String,
+    pub offset: usize,
+    // Runs for the laptop panel, ie. LVDS power scripts
+    pub panel: bool,
+    pub instructions: Vec<Instruction>,
+    pub end: ScriptEnd,
+}
+
+impl Script {
+    pub fn uses_gpio(&self) -> bool {
+        self.instructions.iter().any(|instruction| GPIO_OPCODES.contains(&instruction.code))
+    }
+
+    // Nothing but INIT_DONE
+    pub fn is_empty(&self) -> bool {
+        self.instructions.iter().all(|instruction| instruction.code == INIT_DONE)
+    }
+
+    pub fn summary(&self) -> String {
+        let end = match self.end {
+            ScriptEnd::Done => "ends with INIT_DONE".to_owned(),
+            ScriptEnd::Jump(target) => format!("jumps to {:#x}", target),
+            ScriptEnd::UnknownOpcode(code) => format!("stops at unknown opcode {:#04x}", code),
+            ScriptEnd::UnknownLength(code) => format!("stops at {}, its length depends on the memory straps",
+                find_opcode(code).map_or("UNKNOWN", |opcode| opcode.name)),
+            ScriptEnd::Truncated => "runs past the end of the ROM".to_owned(),
+            ScriptEnd::TooLong => format!("is longer than {} instructions", MAX_INSTRUCTIONS),
+        };
+
+        let gpio = if self.uses_gpio() { ", drives GPIOs" } else { "" };
+        format!("{} instructions, {}{}", self.instructions.len(), end, gpio)
+    }
+}
+
+fn read_operand(rom: &[u8], offset: usize, size: usize) -> u32 {
+    match size {
+        1 => rom[offset] as u32,
+        2 => util::read_uint_16_le(rom, offset) as u32,
+        4 => util::read_uint_32_le(rom, offset),
+        // Only INIT_RESERVED has these, the value doesn't mean anything
+        _ => 0,
+    }
+}
+
+fn decode_instruction(rom: &[u8], offset: usize, opcode: &Opcode) -> Option<Instruction> {
+    let mut pos = offset + 1;
+    let mut operands: Vec<u32> = vec![];
+    for &(_, size) in opcode.operands {
+        if pos + size > rom.len() {
+            return None;
+        }
+        operands.push(read_operand(rom, pos, size));
+        pos += size;
+    }
+
+    let mut items: Vec<u32> = vec![];
+    if let Some((count, size)) = opcode.items {
+        for _ in 0..operands[count] {
+            if pos + size > rom.len() {
+                return None;
+            }
+            items.push(read_operand(rom, pos, size));
+            pos += size;
+        }
+    }
+
+    Some(Instruction { offset, code: opcode.code, operands, items, len: pos - offset })
+}
+
+// Walks the script in a straight line, following neither conditions nor INIT_SUB
+pub fn disassemble(rom: &[u8], offset: usize, label: String) -> Script {
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut pos = offset;
+
+    let end = loop {
+        if instructions.len() >= MAX_INSTRUCTIONS {
+            break ScriptEnd::TooLong;
+        }
+
+        let code = match rom.get(pos) {
+            Some(&code) => code,
+            None => break ScriptEnd::Truncated,
+        };
+        let opcode = match find_opcode(code) {
+            Some(opcode) => opcode,
+            None => break ScriptEnd::UnknownOpcode(code),
+        };
+        if opcode.flow == Flow::Unknown {
+            break ScriptEnd::UnknownLength(code);
+        }
+
+        let instruction = match decode_instruction(rom, pos, opcode) {
+            Some(instruction) => instruction,
+            None => break ScriptEnd::Truncated,
+        };
+        pos += instruction.len;
+
+        let jump = instruction.operands.first().copied();
+        instructions.push(instruction);
+        match opcode.flow {
+            Flow::Done => break ScriptEnd::Done,
+            Flow::Jump => break ScriptEnd::Jump(jump.unwrap_or(0) as usize),
+            _ => {}
+        }
+    };
+
+    Script { label, offset, panel: false, instructions, end }
+}
+
+fn format_value(value: u32, size: usize) -> String {
+    format!("{:#0width$x}", value, width = size * 2 + 2)
+}
+
+pub fn format_instruction(rom: &[u8], instruction: &Instruction) -> String {
+    let opcode = find_opcode(instruction.code);
+
+    // Long groups only show their first bytes
+    let raw = &rom[instruction.offset..instruction.offset + instruction.len.min(8)];
+    let mut hex = raw.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ");
+    if instruction.len > 8 {
+        hex += " ..";
+    }
+
+    let mut text = format!("{:#07x}  {:<26} {}", instruction.offset, hex, instruction.name());
+    if let Some(opcode) = opcode {
+        for (&(name, size), &value) in opcode.operands.iter().zip(instruction.operands.iter()) {
+            text += &format!(" {} {}", name, format_value(value, size));
+        }
+
+        if let Some((_, size)) = opcode.items {
+            let items: Vec<String> = instruction.items.iter().map(|&item| format_value(item, size)).collect();
+            text += &format!(" [{}]", items.join(", "));
+        }
+    }
+
+    text
+}
+
+// List of script pointers ending in 0, used by both BIT 'I' and the BMP
+fn read_script_table(rom: &[u8], table: usize) -> Vec<usize> {
+    let mut scripts: Vec<usize> = vec![];
+    for i in 0..MAX_TABLE_SCRIPTS {
+        let entry = table + i * 2;
+        if entry + 2 > rom.len() {
+            break;
+        }
+
+        match util::read_uint_16_le(rom, entry) {
+            0 => break,
+            script => scripts.push(script as usize),
+        }
+    }
+
+    scripts
+}
+
+fn init_script_table(vbios: &Vbios) -> Option<usize> {
+    if let Some(bmp) = &vbios.bmp {
+        return bmp.init_script_table;
+    }
+
+    let entry = vbios.bit.as_ref()?.entries.iter().find(|entry| entry.id == BIT_INIT_ID)?;
+    let field = entry.pointer + BIT_INIT_SCRIPT_TABLE_OFFSET;
+    if entry.size < BIT_INIT_SCRIPT_TABLE_OFFSET + 2 || field + 2 > vbios.rom.len() {
+        return None;
+    }
+
+    match util::read_uint_16_le(&vbios.rom, field) {
+        0 => None,
+        table => Some(table as usize),
+    }
+}
+
+// nouveau matches output script entries on (location << 4 | type) and (link << 6 | or)
+fn outp_matches(rom: &[u8], entry: &DcbEntry, entry_size: usize, outp_type: u16, outp_mask: u32) -> bool {
+    let link = match entry.entry_type {
+        DCB_CONN_TMDS | DCB_CONN_LVDS | DCB_CONN_DP if entry_size >= 8 && entry.offset + 8 <= rom.len() =>
+            nvidia::get_bits(util::read_uint_32_le(rom, entry.offset + 4), (DCB_LINK_SHIFT, 2)),
+        _ => 0,
+    };
+
+    let dcb_type = (entry.loc << 4 | entry.entry_type) as u16;
+    let dcb_mask = link << 6 | entry.output_resources;
+    outp_type & 0xff == dcb_type & 0xff && outp_mask & dcb_mask == dcb_mask
+}
+
+// Per-output scripts from BIT 'U', labelled with the DCB entry they belong to
+fn display_scripts(vbios: &Vbios) -> Vec<(String, usize, bool)> {
+    let rom = &vbios.rom;
+    let mut scripts: Vec<(String, usize, bool)> = vec![];
+
+    let entry = match vbios.bit.as_ref().and_then(|bit| bit.entries.iter().find(|entry| entry.id == BIT_DISPLAY_SCRIPTS_ID)) {
+        Some(entry) if entry.version == 1 && entry.size >= 2 && entry.pointer + 2 <= rom.len() => entry,
+        _ => return scripts,
+    };
+
+    let table = util::read_uint_16_le(rom, entry.pointer) as usize;
+    if table == 0 || table + DISP_SUB_SIZE_OFFSET >= rom.len() || !DISP_TABLE_VERSIONS.contains(&rom[table]) {
+        return scripts;
+    }
+
+    let header_size = rom[table + DISP_HEADER_SIZE_OFFSET] as usize;
+    let entry_size = rom[table + DISP_ENTRY_SIZE_OFFSET] as usize;
+    let count = rom[table + DISP_COUNT_OFFSET] as usize;
+    let sub_size = rom[table + DISP_SUB_SIZE_OFFSET] as usize;
+    // Too short to have the first two scripts
+    if sub_size < OUTP_SCRIPTS_OFFSET + 4 || entry_size < 2 {
+        return scripts;
+    }
+
+    for i in 0..count {
+        let disp = table + header_size + i * entry_size;
+        if disp + 2 > rom.len() {
+            break;
+        }
+
+        let outp = util::read_uint_16_le(rom, disp) as usize;
+        if outp == 0 || outp + sub_size > rom.len() {
+            continue;
+        }
+
+        let outp_type = util::read_uint_16_le(rom, outp + OUTP_TYPE_OFFSET);
+        let outp_mask = util::read_uint_32_le(rom, outp + OUTP_MASK_OFFSET);
+        let dcb = vbios.dcb_entries.iter()
+            .find(|dcb| outp_matches(rom, dcb, vbios.dcb.entry_size as usize, outp_type, outp_mask));
+        let owner = match dcb {
+            Some(dcb) => format!("DCB entry {:#x} ({})", dcb.entry, nvidia::dcb_type_to_string(dcb.entry_type)),
+            None => format!("Output {} (type {:#06x}, no DCB entry)", i, outp_type),
+        };
+        let panel = dcb.is_some_and(|dcb| nvidia::entry_is_internal(dcb, &vbios.connectors));
+
+        let count = if sub_size >= OUTP_SCRIPTS_OFFSET + 6 { 3 } else { 2 };
+        for script in 0..count {
+            let pointer = util::read_uint_16_le(rom, outp + OUTP_SCRIPTS_OFFSET + script * 2) as usize;
+            if pointer != 0 {
+                scripts.push((format!("{} script {}", owner, script), pointer, panel));
+            }
+        }
+    }
+
+    scripts
+}
+
+// Every script this tool can find a pointer to
+pub fn find_scripts(vbios: &Vbios) -> Vec<Script> {
+    // Label, offset and whether the script is for the panel
+    let mut pointers: Vec<(String, usize, bool)> = vec![];
+
+    if let Some(table) = init_script_table(vbios) {
+        for (i, script) in read_script_table(&vbios.rom, table).into_iter().enumerate() {
+            pointers.push((format!("Init script {}", i), script, false));
+        }
+    }
+
+    if let Some(lvds) = &vbios.lvds {
+        for (i, &script) in lvds.power_on_scripts.iter().enumerate() {
+            pointers.push((format!("LVDS power on script {}", i), script, true));
+        }
+        for (i, &script) in lvds.power_off_scripts.iter().enumerate() {
+            pointers.push((format!("LVDS power off script {}", i), script, true));
+        }
+    }
+
+    pointers.extend(display_scripts(vbios));
+
+    pointers.into_iter()
+        .map(|(label, offset, panel)| Script { panel, ..disassemble(&vbios.rom, offset, label) })
+        .collect()
+}
+
+// Whether the panel scripts look like they handle power and backlight
+pub fn describe_backlight(scripts: &[Script]) -> String {
+    let panel: Vec<&Script> = scripts.iter().filter(|script| script.panel && !script.is_empty()).collect();
+
+    if panel.is_empty() {
+        "No panel scripts found, power and backlight are likely left to the driver".to_owned()
+    } else if panel.iter().any(|script| script.uses_gpio()) {
+        "Panel scripts drive GPIOs, so power/backlight is likely done by the VBIOS; Script Based Power/Backlight is worth setting".to_owned()
+    } else {
+        "Panel scripts exist but don't touch GPIOs, backlight is probably not scripted".to_owned()
+    }
+}
+
+fn print_script(rom: &[u8], script: &Script) {
+    util::header();
+    println!("{} at {:#x}", script.label.bright_blue(), script.offset);
+    println!("{}", script.summary());
+    println!();
+
+    for instruction in &script.instructions {
+        let line = format_instruction(rom, instruction);
+        if GPIO_OPCODES.contains(&instruction.code) {
+            println!("{}", line.yellow());
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    println!();
+    util::press_any_key();
+}
+
+// Lists every script found, and shows the one picked
+pub fn view_scripts(vbios: &Vbios) {
+    let scripts = find_scripts(vbios);
+
+    loop {
+        let mut input = String::new();
+        util::header();
+
+        if scripts.is_empty() {
+            println!("No init scripts found in this ROM");
+            println!();
+            util::press_any_key();
+            return;
+        }
+
+        for (i, script) in scripts.iter().enumerate() {
+            println!("{} {} at {:#x}: {}", format!("({})", i + 1).bright_blue(), script.label, script.offset, script.summary());
+        }
+
+        println!();
+        println!("{} {}", "Backlight:".green(), describe_backlight(&scripts));
+        println!();
+        util::prompt("Select a script to disassemble, or enter to go back: ", &mut input);
+        match input.trim().parse::<usize>() {
+            Ok(idx) if (1..=scripts.len()).contains(&idx) => print_script(&vbios.rom, &scripts[idx - 1]),
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmp::BMP_INIT_SCRIPT_TABLE_OFFSET;
+    use crate::test_support::*;
+
+    #[test]
+    fn disassembles_script() {
+        let mut rom = vec![0u8; 4];
+        rom.extend_from_slice(&[0x7a, 0x00, 0x41, 0x61, 0x00, 0x18, 0x00, 0x00, 0x10]);
+        rom.extend_from_slice(&[0x58, 0x00, 0x10, 0x00, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]);
+        rom.extend_from_slice(&[0x8e, 0x74, 0x10, 0x00, INIT_DONE]);
+        let script = disassemble(&rom, 4, "test".to_owned());
+
+        assert_eq!(script.end, ScriptEnd::Done);
+        assert!(script.uses_gpio());
+        let names: Vec<&str> = script.instructions.iter().map(|instruction| instruction.name()).collect();
+        assert_eq!(names, ["INIT_ZM_REG", "INIT_ZM_REG_SEQUENCE", "INIT_GPIO", "INIT_TIME", "INIT_DONE"]);
+        assert_eq!(format_instruction(&rom, &script.instructions[0]),
+            "0x00004  7a 00 41 61 00 18 00 00 .. INIT_ZM_REG reg 0x00614100 data 0x10000018");
+        assert_eq!(script.instructions[1].items, [1, 2]);
+    }
+
+    #[test]
+    fn opcode_lengths_match_nouveau() {
+        // Length of each opcode including the opcode byte, and the size of its trailing
+        // items, as nouveau's init.c advances init->offset
+        let lengths = [
+            (0x32, 11, 4), (0x33, 2, 0), (0x34, 12, 2), (0x36, 1, 0), (0x37, 11, 0), (0x38, 1, 0),
+            (0x39, 2, 0), (0x3a, 3, 0), (0x3b, 2, 0), (0x3c, 2, 0), (0x47, 9, 0), (0x48, 9, 0),
+            (0x49, 18, 2), (0x4a, 11, 4), (0x4b, 9, 0), (0x4c, 4, 3), (0x4d, 4, 2), (0x4e, 4, 1),
+            (0x4f, 5, 0), (0x50, 3, 2), (0x51, 5, 1), (0x52, 4, 0), (0x53, 3, 0), (0x54, 2, 2),
+            (0x56, 3, 0), (0x57, 3, 0), (0x58, 6, 4), (0x59, 7, 0), (0x5a, 7, 0), (0x5b, 3, 0),
+            (0x5c, 3, 0), (0x5e, 6, 0), (0x5f, 22, 0), (0x62, 5, 0), (0x63, 1, 0), (0x65, 13, 0),
+            (0x66, 1, 0), (0x67, 1, 0), (0x68, 1, 0), (0x69, 5, 0), (0x6b, 2, 0), (0x6d, 3, 0),
+            (0x6e, 13, 0), (0x6f, 2, 0), (0x71, 1, 0), (0x72, 1, 0), (0x73, 9, 0), (0x74, 3, 0),
+            (0x75, 2, 0), (0x76, 2, 0), (0x77, 7, 0), (0x78, 6, 0), (0x79, 7, 0), (0x7a, 9, 0),
+            (0x87, 2, 0), (0x8c, 1, 0), (0x8d, 1, 0), (0x8e, 1, 0), (0x8f, 7, 0), (0x90, 9, 0),
+            (0x91, 6, 4), (0x92, 1, 0), (0x96, 17, 0), (0x97, 13, 0), (0x98, 6, 2), (0x99, 6, 1),
+            (0x9a, 7, 0), (0xa9, 2, 1), (0xaa, 4, 0),
+        ];
+
+        assert_eq!(lengths.len(), OPCODES.len());
+        for (code, len, item_size) in lengths {
+            let opcode = find_opcode(code).unwrap();
+            let operands: usize = opcode.operands.iter().map(|(_, size)| size).sum();
+            assert_eq!(1 + operands, len, "{}", opcode.name);
+            assert_eq!(opcode.items.map_or(0, |(_, size)| size), item_size, "{}", opcode.name);
+        }
+    }
+
+    #[test]
+    fn stops_where_it_cant_follow() {
+        assert_eq!(disassemble(&[0x5c, 0x34, 0x12], 0, "".to_owned()).end, ScriptEnd::Jump(0x1234));
+        assert_eq!(disassemble(&[0x38, 0x00], 0, "".to_owned()).end, ScriptEnd::UnknownOpcode(0));
+        assert_eq!(disassemble(&[0x87, 0x00], 0, "".to_owned()).end, ScriptEnd::UnknownLength(0x87));
+        assert_eq!(disassemble(&[0x7a, 0x00, 0x41], 0, "".to_owned()).end, ScriptEnd::Truncated);
+        assert_eq!(disassemble(&[0x38; 2000], 0, "".to_owned()).end, ScriptEnd::TooLong);
+    }
+
+    #[test]
+    fn finds_init_and_display_scripts() {
+        let builder = RomBuilder::new()
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+            .entry(dcb_entry(CRT, 0, 3, 1, 0))
+            .bmp(5, 0x25);
+
+        // Script table followed by two scripts, the second one switching the panel on
+        let table = builder.data_offset();
+        let mut data = ((table + 6) as u16).to_le_bytes().to_vec();
+        data.extend(((table + 7) as u16).to_le_bytes());
+        data.extend([0, 0, INIT_DONE, 0x8e, INIT_DONE]);
+
+        let rom = builder.bmp_pointer(BMP_INIT_SCRIPT_TABLE_OFFSET, &data).build();
+        let vbios = nvidia::parse_rom(&rom).unwrap();
+        let scripts = find_scripts(&vbios);
+
+        let labels: Vec<&str> = scripts.iter().map(|script| script.label.as_str()).collect();
+        assert_eq!(labels, ["Init script 0", "Init script 1"]);
+        assert!(scripts[0].is_empty());
+        assert!(scripts[1].uses_gpio());
+        assert_eq!(describe_backlight(&scripts), "No panel scripts found, power and backlight are likely left to the driver");
+
+        // Display scripts hang off BIT 'U', matched to the DCB entries
+        let mut dcb = RomBuilder::new()
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0) | 1 << 24)
+            .entry(dcb_entry(CRT, 0, 3, 1, 0) | 2 << 24);
+        let base = dcb.data_offset();
+        let disp = base + 2;
+        let outp = disp + 5 + 2 * 2;
+        let mut data = (disp as u16).to_le_bytes().to_vec();
+        data.extend([0x20, 5, 2, 2, 0xc]);
+        data.extend((outp as u16).to_le_bytes());
+        data.extend(((outp + 0xc) as u16).to_le_bytes());
+        // LVDS on OR 0 with a GPIO script, CRT on OR 1 with an empty one
+        data.extend([LVDS as u8, 0, 1, 0, 0, 0]);
+        data.extend(((outp + 0x18) as u16).to_le_bytes());
+        data.extend([0, 0, 0, 0]);
+        data.extend([CRT as u8, 0, 2, 0, 0, 0]);
+        data.extend(((outp + 0x1a) as u16).to_le_bytes());
+        data.extend([0, 0, 0, 0]);
+        data.extend([0x8e, INIT_DONE, INIT_DONE]);
+        dcb = dcb.bit_entry(b'U', 1, &data);
+
+        let vbios = nvidia::parse_rom(&dcb.build()).unwrap();
+        let scripts = find_scripts(&vbios);
+        let labels: Vec<&str> = scripts.iter().map(|script| script.label.as_str()).collect();
+        assert_eq!(labels, ["DCB entry 0x0 (LVDS) script 0", "DCB entry 0x1 (CRT) script 0"]);
+        assert!(describe_backlight(&scripts).starts_with("Panel scripts drive GPIOs"));
+    }
+}
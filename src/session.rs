@@ -0,0 +1,330 @@
+use std::fs;
+use std::path::Path;
+use colored::*;
+use crate::ioreg::IoregDevice;
+use crate::nvidia::{self, MergeStrategy, NVCAP, NVCAP_SIZE};
+use crate::util::{self, Display, NVErrors};
+
+const SESSION_HEADER: &str = "# NVCAP Calculator session";
+// Without these a session would silently fall back to zeroes
+const REQUIRED_KEYS: [&str; 3] = ["rom", "version", "field_f"];
+
+// Everything needed to pick up an NVCAP calculation where it was left off
+#[derive(Clone, PartialEq)]
+pub struct Session {
+    pub rom_path: String,
+    pub rom_crc32: u32,
+    // Head assignments are display indexes, which depend on how entries were merged
+    pub merge: MergeStrategy,
+    pub head_tv: Vec<usize>,
+    pub head_0: Vec<usize>,
+    pub head_1: Vec<usize>,
+    pub nvcap: NVCAP,
+    // Where the GPU sits for OpenCore/Clover, imported from ioreg
+    pub device_path: Option<String>,
+}
+
+impl Session {
+    // Keep the GPU's device path from an ioreg dump for the config.plist snippets
+    pub fn import_device_path(&mut self, device: &IoregDevice) -> Option<&str> {
+        if let Some(path) = &device.pci_path {
+            self.device_path = Some(path.clone());
+        }
+
+        self.device_path.as_deref()
+    }
+
+    // Take a whole NVCAP value, turning the head masks back into display assignments.
+    // Returns warnings about mask bits that can't be represented that way.
+    pub fn apply_nvcap_bytes(&mut self, displays: &[Display], bytes: &[u8; NVCAP_SIZE]) -> Vec<String> {
+        let nvcap = NVCAP::from_bytes(bytes);
+        let mut warnings: Vec<String> = vec![];
+        let masks = [
+            ("TV", nvcap.dcb_tv_mask, &mut self.head_tv),
+            ("1", nvcap.dcb_0_mask, &mut self.head_0),
+            ("2", nvcap.dcb_1_mask, &mut self.head_1),
+        ];
+
+        for (name, mask, head) in masks {
+            let (displays_on_head, leftover) = nvidia::heads_from_mask(mask, displays);
+            if leftover != 0 {
+                warnings.push(format!("Head {} mask bits {:#x} don't match a whole display and were dropped",
+                    name, leftover));
+            }
+
+            *head = displays_on_head;
+        }
+
+        self.nvcap = nvcap;
+        warnings
+    }
+
+    // Raw byte edits, either "<offset> <value>" or the whole NVCAP value.
+    // Returns the warnings from apply_nvcap_bytes, or why the input wasn't understood.
+    pub fn apply_raw_input(&mut self, displays: &[Display], input: &str) -> Result<Vec<String>, String> {
+        nvidia::update_head_masks(&mut self.nvcap, displays, &self.head_tv, &self.head_0, &self.head_1);
+
+        let mut bytes = self.nvcap.to_bytes();
+        let args: Vec<&str> = input.split_whitespace().collect();
+        if args.len() == 2 && args[1].trim_start_matches("0x").len() <= 2 {
+            let offset = usize::from_str_radix(args[0].trim_start_matches("0x"), 16);
+            let value = u8::from_str_radix(args[1].trim_start_matches("0x"), 16);
+            match (offset, value) {
+                (Ok(offset), Ok(value)) if offset < NVCAP_SIZE => bytes[offset] = value,
+                _ => return Err(format!("\"{}\" is not an offset and a hex byte", input)),
+            }
+        } else {
+            match util::parse_hex_bytes(input) {
+                Some(all) if all.len() == NVCAP_SIZE => bytes.copy_from_slice(&all),
+                _ => return Err(format!("Expected {} hex bytes", NVCAP_SIZE)),
+            }
+        }
+
+        Ok(self.apply_nvcap_bytes(displays, &bytes))
+    }
+}
+
+fn join_heads(head: &[usize]) -> String {
+    head.iter().map(|disp| (disp + 1).to_string()).collect::<Vec<String>>().join(",")
+}
+
+fn parse_heads(value: &str) -> Result<Vec<usize>, NVErrors> {
+    let mut head: Vec<usize> = vec![];
+    for disp in value.split(',').map(|disp| disp.trim()).filter(|disp| !disp.is_empty()) {
+        match disp.parse::<usize>() {
+            Ok(idx) if idx > 0 => head.push(idx - 1),
+            _ => return Err(NVErrors::Corrupted),
+        }
+    }
+
+    Ok(head)
+}
+
+fn parse_hex(value: &str) -> Result<u32, NVErrors> {
+    u32::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| NVErrors::Corrupted)
+}
+
+fn parse_u8(value: &str) -> Result<u8, NVErrors> {
+    let val = parse_hex(value)?;
+    if val > u8::MAX as u32 {
+        return Err(NVErrors::Corrupted);
+    }
+
+    Ok(val as u8)
+}
+
+fn parse_bool(value: &str) -> Result<bool, NVErrors> {
+    value.parse::<bool>().map_err(|_| NVErrors::Corrupted)
+}
+
+fn show_heads(head: &[usize]) -> String {
+    format!("[{}]", join_heads(head))
+}
+
+// Human readable list of what differs between two states, for the edit history
+pub fn describe_changes(before: &Session, after: &Session) -> String {
+    let mut changes: Vec<String> = vec![];
+    let mut changed = |name: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("{}: {} -> {}", name, old, new));
+        }
+    };
+
+    changed("TV head", show_heads(&before.head_tv), show_heads(&after.head_tv));
+    changed("Head 1", show_heads(&before.head_0), show_heads(&after.head_0));
+    changed("Head 2", show_heads(&before.head_1), show_heads(&after.head_1));
+
+    let (old, new) = (&before.nvcap, &after.nvcap);
+    changed("Version", old.version.to_string(), new.version.to_string());
+    changed("Mobile", old.is_mobile.to_string(), new.is_mobile.to_string());
+    changed("Composite", old.is_composite.to_string(), new.is_composite.to_string());
+    changed("Unknown 1", format!("{:#x}", old.unknown_1), format!("{:#x}", new.unknown_1));
+    changed("Script Based Power/Backlight", old.script_based_power_and_backlight.to_string(),
+            new.script_based_power_and_backlight.to_string());
+    changed("Field F", format!("{:#x}", old.field_f), format!("{:#x}", new.field_f));
+    changed("EDID Bitness", format!("{:#x}", old.edid_bitness), format!("{:#x}", new.edid_bitness));
+    changed("Unknown 2", format!("{:x?}", old.unknown_2), format!("{:x?}", new.unknown_2));
+    changed("Device path", before.device_path.clone().unwrap_or_default(), after.device_path.clone().unwrap_or_default());
+
+    changes.join(", ")
+}
+
+pub fn is_session_file(path: &str) -> bool {
+    match fs::read(path) {
+        Ok(bytes) => bytes.starts_with(SESSION_HEADER.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+// The session may be loaded from another directory, so the ROM path is stored as an absolute path
+fn absolute_path(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => path.to_owned(),
+    }
+}
+
+// Older sessions have paths relative to wherever they were saved from, try next to the session first
+fn resolve_rom_path(session_path: &str, rom_path: &str) -> String {
+    let rom = Path::new(rom_path);
+    let dir = Path::new(session_path).parent().unwrap_or_else(|| Path::new(""));
+    if rom.is_relative() && dir.join(rom).exists() {
+        return dir.join(rom).to_string_lossy().to_string();
+    }
+
+    rom_path.to_owned()
+}
+
+pub fn save(path: &str, session: &Session) -> Result<(), NVErrors> {
+    let nvcap = &session.nvcap;
+    let mut out = String::new();
+
+    out += &format!("{}\n", SESSION_HEADER);
+    out += &format!("rom = {}\n", absolute_path(&session.rom_path));
+    out += &format!("rom_crc32 = {:#010x}\n", session.rom_crc32);
+    out += &format!("merge = {}\n", session.merge.name());
+    out += &format!("head_tv = {}\n", join_heads(&session.head_tv));
+    out += &format!("head_0 = {}\n", join_heads(&session.head_0));
+    out += &format!("head_1 = {}\n", join_heads(&session.head_1));
+    out += &format!("version = {:#x}\n", nvcap.version);
+    out += &format!("is_mobile = {}\n", nvcap.is_mobile);
+    out += &format!("is_composite = {}\n", nvcap.is_composite);
+    out += &format!("unknown_1 = {:#x}\n", nvcap.unknown_1);
+    out += &format!("script_based_power_and_backlight = {}\n", nvcap.script_based_power_and_backlight);
+    out += &format!("field_f = {:#x}\n", nvcap.field_f);
+    out += &format!("edid_bitness = {:#x}\n", nvcap.edid_bitness);
+    out += &format!("unknown_2 = {:#x},{:#x},{:#x}\n", nvcap.unknown_2[0], nvcap.unknown_2[1], nvcap.unknown_2[2]);
+    if let Some(path) = &session.device_path {
+        out += &format!("device_path = {}\n", path);
+    }
+
+    match fs::write(path, out) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            println!("{}", "Unable to save session!".red());
+            println!("{}", e);
+            Err(NVErrors::FileNotFound)
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<Session, NVErrors> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("{}", "Session file not found!".red());
+            println!("{}", e);
+            return Err(NVErrors::FileNotFound);
+        }
+    };
+
+    let mut session = Session {
+        rom_path: String::new(),
+        rom_crc32: 0,
+        merge: MergeStrategy::Bus,
+        head_tv: vec![],
+        head_0: vec![],
+        head_1: vec![],
+        nvcap: NVCAP::default(),
+        device_path: None,
+    };
+
+    let mut seen: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                println!("{} {}", "Invalid session line:".red(), line);
+                return Err(NVErrors::Corrupted);
+            }
+        };
+
+        seen.push(key.to_owned());
+        let nvcap = &mut session.nvcap;
+        match key {
+            "rom" => session.rom_path = value.to_owned(),
+            "rom_crc32" => session.rom_crc32 = parse_hex(value)?,
+            "merge" => session.merge = MergeStrategy::from_name(value).ok_or(NVErrors::Corrupted)?,
+            "head_tv" => session.head_tv = parse_heads(value)?,
+            "head_0" => session.head_0 = parse_heads(value)?,
+            "head_1" => session.head_1 = parse_heads(value)?,
+            "version" => nvcap.version = parse_u8(value)?,
+            "is_mobile" => nvcap.is_mobile = parse_bool(value)?,
+            "is_composite" => nvcap.is_composite = parse_bool(value)?,
+            "unknown_1" => nvcap.unknown_1 = parse_u8(value)?,
+            "script_based_power_and_backlight" => nvcap.script_based_power_and_backlight = parse_bool(value)?,
+            "field_f" => nvcap.field_f = parse_u8(value)?,
+            "edid_bitness" => nvcap.edid_bitness = parse_u8(value)?,
+            "device_path" => session.device_path = Some(value.to_owned()),
+            "unknown_2" => {
+                let bytes: Vec<&str> = value.split(',').map(|byte| byte.trim()).collect();
+                if bytes.len() != nvcap.unknown_2.len() {
+                    return Err(NVErrors::Corrupted);
+                }
+
+                for (i, byte) in bytes.iter().enumerate() {
+                    nvcap.unknown_2[i] = parse_u8(byte)?;
+                }
+            }
+            _ => println!("{} {}", "Ignoring unknown session field:".yellow(), key),
+        }
+    }
+
+    let missing: Vec<&str> = REQUIRED_KEYS.iter().copied().filter(|key| !seen.iter().any(|seen| seen == key)).collect();
+    if !missing.is_empty() || session.rom_path.is_empty() {
+        println!("{} {}", "Session is missing:".red(), missing.join(", "));
+        return Err(NVErrors::Corrupted);
+    }
+
+    session.rom_path = resolve_rom_path(path, &session.rom_path);
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = env::temp_dir().join(format!("nvcap_session_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("board.rom");
+        fs::write(&rom, [0u8; 4]).unwrap();
+        let path = dir.join("board.rom.nvcap").to_string_lossy().to_string();
+
+        let nvcap = NVCAP { version: 5, is_mobile: true, field_f: 0x0f, unknown_2: [1, 2, 3], ..NVCAP::default() };
+        let session = Session {
+            rom_path: rom.to_string_lossy().to_string(),
+            rom_crc32: 0x1234abcd,
+            merge: MergeStrategy::Connector,
+            head_tv: vec![3],
+            head_0: vec![0],
+            head_1: vec![1, 2],
+            nvcap,
+            device_path: Some("PciRoot(0x0)/Pci(0x1,0x0)/Pci(0x0,0x0)".to_owned()),
+        };
+
+        save(&path, &session).unwrap();
+        let loaded = load(&path).unwrap();
+        assert!(loaded == Session { rom_path: absolute_path(&session.rom_path), ..session.clone() });
+
+        // Sessions saved with a relative path find the ROM next to them
+        let text = fs::read_to_string(&path).unwrap();
+        let relative = text.replace(&absolute_path(&session.rom_path), "board.rom");
+        fs::write(&path, &relative).unwrap();
+        assert_eq!(Path::new(&load(&path).unwrap().rom_path), dir.join("board.rom"));
+
+        // Missing fields are an error rather than zeroes
+        fs::write(&path, relative.replace("field_f = 0xf\n", "")).unwrap();
+        assert!(load(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
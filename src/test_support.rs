@@ -0,0 +1,300 @@
+// Synthetic VBIOS images for tests, since real ROMs can't be committed
+
+const PCIR_OFFSET: usize = 0x40;
+const PCIR_LENGTH: usize = 0x18;
+const BIT_OFFSET: usize = 0x60;
+const BIT_HEADER_SIZE: usize = 0xc;
+const BIT_ENTRY_SIZE: usize = 6;
+// Old ROMs have a BMP where newer ones have the BIT
+const BMP_OFFSET: usize = 0x60;
+const BMP_I2C_OFFSET: usize = 54;
+const ROM_BLOCK_SIZE: usize = 512;
+
+pub const DCB_SIGNATURE: u32 = 0x4edcbdcb;
+pub const DCB_ENTRY_SKIP: u32 = 0x0000000f;
+pub const DCB_ENTRY_EOL: u32 = 0x0000000e;
+
+// DCB output types
+pub const CRT: u32 = 0;
+pub const TV: u32 = 1;
+pub const TMDS: u32 = 2;
+pub const LVDS: u32 = 3;
+pub const SDI: u32 = 5;
+pub const DP: u32 = 6;
+
+// Connector table types
+pub const CONN_VGA: u8 = 0x00;
+pub const CONN_DVI_I: u8 = 0x30;
+pub const CONN_DVI_D: u8 = 0x31;
+pub const CONN_DP: u8 = 0x46;
+pub const CONN_EDP: u8 = 0x47;
+pub const CONN_HDMI: u8 = 0x60;
+
+const CONN_TABLE_HEADER_SIZE: usize = 5;
+const CONN_TABLE_ENTRY_SIZE: usize = 4;
+
+// First 32 bits of a DCB entry
+pub fn dcb_entry(entry_type: u32, edid_port: u32, heads: u32, con: u32, bus: u32) -> u32 {
+    entry_type | edid_port << 4 | heads << 8 | con << 12 | bus << 16
+}
+
+pub struct RomBuilder {
+    pci: Option<(u16, u16)>,
+    dcb_offset: usize,
+    version: u8,
+    header_size: u8,
+    entry_size: u8,
+    signature: u32,
+    entries: Vec<u32>,
+    // Type and the byte after it (location and hotplug pins)
+    connectors: Vec<u8>,
+    raw_dcb: Option<Vec<u8>>,
+    // Tables the BIT and BMP point at, laid out one after another past the DCB
+    data: Vec<Vec<u8>>,
+    bit_entries: Vec<(u8, u8, usize)>,
+    bmp: Option<(u8, u8)>,
+    bmp_pointers: Vec<(usize, usize)>,
+}
+
+impl RomBuilder {
+    // A DCB 4.0 ROM for a GeForce 8600 GT with no entries yet
+    pub fn new() -> RomBuilder {
+        RomBuilder {
+            pci: Some((0x10de, 0x0402)),
+            dcb_offset: 0x100,
+            version: 0x40,
+            header_size: 0x17,
+            entry_size: 8,
+            signature: DCB_SIGNATURE,
+            entries: vec![],
+            connectors: vec![],
+            raw_dcb: None,
+            data: vec![],
+            bit_entries: vec![],
+            bmp: None,
+            bmp_pointers: vec![],
+        }
+    }
+
+    pub fn pci(mut self, vendor_id: u16, device_id: u16) -> RomBuilder {
+        self.pci = Some((vendor_id, device_id));
+        self
+    }
+
+    pub fn no_pci(mut self) -> RomBuilder {
+        self.pci = None;
+        self
+    }
+
+    pub fn dcb_offset(mut self, offset: usize) -> RomBuilder {
+        self.dcb_offset = offset;
+        self
+    }
+
+    pub fn version(mut self, version: u8) -> RomBuilder {
+        self.version = version;
+        self
+    }
+
+    pub fn header_size(mut self, size: u8) -> RomBuilder {
+        self.header_size = size;
+        self
+    }
+
+    pub fn signature(mut self, signature: u32) -> RomBuilder {
+        self.signature = signature;
+        self
+    }
+
+    pub fn entry(mut self, conn: u32) -> RomBuilder {
+        self.entries.push(conn);
+        self
+    }
+
+    pub fn skip(self) -> RomBuilder {
+        self.entry(DCB_ENTRY_SKIP)
+    }
+
+    pub fn eol(self) -> RomBuilder {
+        self.entry(DCB_ENTRY_EOL)
+    }
+
+    // Adds a connector table entry at location 0 without hotplug pins, the table goes right
+    // after the DCB entries
+    pub fn connector(mut self, conn_type: u8) -> RomBuilder {
+        self.connectors.push(conn_type);
+        self
+    }
+
+    // A DCB region copied out of a real ROM, used instead of the header and entries
+    pub fn raw_dcb(mut self, dcb: &[u8]) -> RomBuilder {
+        self.raw_dcb = Some(dcb.to_vec());
+        self
+    }
+
+    // Adds a BIT entry pointing at `data`, which goes after the DCB
+    pub fn bit_entry(mut self, id: u8, version: u8, data: &[u8]) -> RomBuilder {
+        self.bit_entries.push((id, version, self.data.len()));
+        self.data.push(data.to_vec());
+        self
+    }
+
+    // Adds a BMP structure (which old ROMs have instead of a BIT) of the given version
+    pub fn bmp(mut self, major: u8, minor: u8) -> RomBuilder {
+        self.bmp = Some((major, minor));
+        self
+    }
+
+    // Points the BMP field at `field` to `data`, which goes after the DCB
+    pub fn bmp_pointer(mut self, field: usize, data: &[u8]) -> RomBuilder {
+        self.bmp_pointers.push((field, self.data.len()));
+        self.data.push(data.to_vec());
+        self
+    }
+
+    fn dcb_end(&self) -> usize {
+        match &self.raw_dcb {
+            Some(dcb) => self.dcb_offset + dcb.len(),
+            None => self.dcb_offset + self.header_size as usize + self.entries.len() * self.entry_size as usize +
+                CONN_TABLE_HEADER_SIZE + self.connectors.len() * CONN_TABLE_ENTRY_SIZE,
+        }
+    }
+
+    // Where the next data added with bit_entry or bmp_pointer will go, for data that
+    // points into itself. Add DCB entries and connectors first, they come before it.
+    pub fn data_offset(&self) -> usize {
+        self.dcb_end() + self.data.iter().map(|data| data.len()).sum::<usize>()
+    }
+
+    // Where data added with bit_entry or bmp_pointer ends up
+    fn data_offsets(&self) -> Vec<usize> {
+        self.data.iter()
+            .scan(self.dcb_end(), |offset, data| {
+                let start = *offset;
+                *offset += data.len();
+                Some(start)
+            })
+            .collect()
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        assert!(self.bmp.is_none() || self.bit_entries.is_empty(), "a ROM has either a BMP or a BIT");
+
+        let offsets = self.data_offsets();
+        let entries_end = self.data_offset();
+        // Leave room for the checksum byte at the end
+        let blocks = (entries_end + 1).div_ceil(ROM_BLOCK_SIZE);
+        let mut rom = vec![0u8; blocks * ROM_BLOCK_SIZE];
+
+        if let Some((vendor_id, device_id)) = self.pci {
+            rom[0] = 0x55;
+            rom[1] = 0xaa;
+            rom[2] = blocks as u8;
+            rom[0x18..0x1a].copy_from_slice(&(PCIR_OFFSET as u16).to_le_bytes());
+            rom[PCIR_OFFSET..PCIR_OFFSET + 4].copy_from_slice(b"PCIR");
+            rom[PCIR_OFFSET + 4..PCIR_OFFSET + 6].copy_from_slice(&vendor_id.to_le_bytes());
+            rom[PCIR_OFFSET + 6..PCIR_OFFSET + 8].copy_from_slice(&device_id.to_le_bytes());
+            rom[PCIR_OFFSET + 0xa..PCIR_OFFSET + 0xc].copy_from_slice(&(PCIR_LENGTH as u16).to_le_bytes());
+        }
+
+        let dcb = self.dcb_offset;
+        rom[0x36..0x38].copy_from_slice(&(dcb as u16).to_le_bytes());
+        if let Some(raw) = &self.raw_dcb {
+            rom[dcb..dcb + raw.len()].copy_from_slice(raw);
+        } else {
+            self.write_dcb(&mut rom);
+        }
+
+        for (data, &offset) in self.data.iter().zip(offsets.iter()) {
+            rom[offset..offset + data.len()].copy_from_slice(data);
+        }
+
+        if !self.bit_entries.is_empty() {
+            self.write_bit(&mut rom, &offsets);
+        }
+
+        if let Some(version) = self.bmp {
+            self.write_bmp(&mut rom, version, &offsets);
+        }
+
+        if self.pci.is_some() {
+            let last = rom.len() - 1;
+            let sum = rom[..last].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            rom[last] = 0u8.wrapping_sub(sum);
+        }
+
+        rom
+    }
+
+    fn write_bit(&self, rom: &mut [u8], offsets: &[usize]) {
+        rom[BIT_OFFSET..BIT_OFFSET + 6].copy_from_slice(b"\xff\xb8BIT\x00");
+        rom[BIT_OFFSET + 6..BIT_OFFSET + 8].copy_from_slice(&0x0100u16.to_le_bytes());
+        rom[BIT_OFFSET + 8] = BIT_HEADER_SIZE as u8;
+        rom[BIT_OFFSET + 9] = BIT_ENTRY_SIZE as u8;
+        rom[BIT_OFFSET + 10] = self.bit_entries.len() as u8;
+
+        for (i, &(id, version, data)) in self.bit_entries.iter().enumerate() {
+            let entry = BIT_OFFSET + BIT_HEADER_SIZE + i * BIT_ENTRY_SIZE;
+            rom[entry] = id;
+            rom[entry + 1] = version;
+            rom[entry + 2..entry + 4].copy_from_slice(&(self.data[data].len() as u16).to_le_bytes());
+            rom[entry + 4..entry + 6].copy_from_slice(&(offsets[data] as u16).to_le_bytes());
+        }
+    }
+
+    fn write_bmp(&self, rom: &mut [u8], (major, minor): (u8, u8), offsets: &[usize]) {
+        rom[BMP_OFFSET..BMP_OFFSET + 5].copy_from_slice(b"\xff\x7fNV\x00");
+        rom[BMP_OFFSET + 5] = major;
+        rom[BMP_OFFSET + 6] = minor;
+        // BIOS version 05.72.22.24, stored backwards
+        rom[BMP_OFFSET + 10..BMP_OFFSET + 14].copy_from_slice(&[0x24, 0x22, 0x72, 0x05]);
+        // CRT, TV and panel I2C ports
+        rom[BMP_OFFSET + BMP_I2C_OFFSET..BMP_OFFSET + BMP_I2C_OFFSET + 3].copy_from_slice(&[0, 1, 2]);
+
+        for &(field, data) in &self.bmp_pointers {
+            rom[BMP_OFFSET + field..BMP_OFFSET + field + 2].copy_from_slice(&(offsets[data] as u16).to_le_bytes());
+        }
+    }
+
+    fn write_dcb(&self, rom: &mut [u8]) {
+        let dcb = self.dcb_offset;
+        rom[dcb] = self.version;
+        rom[dcb + 1] = self.header_size;
+        rom[dcb + 2] = self.entries.len() as u8;
+        rom[dcb + 3] = self.entry_size;
+        rom[dcb + 6..dcb + 10].copy_from_slice(&self.signature.to_le_bytes());
+
+        for (i, conn) in self.entries.iter().enumerate() {
+            let offset = dcb + self.header_size as usize + i * self.entry_size as usize;
+            rom[offset..offset + 4].copy_from_slice(&conn.to_le_bytes());
+        }
+
+        if self.connectors.is_empty() {
+            return;
+        }
+
+        let table = dcb + self.header_size as usize + self.entries.len() * self.entry_size as usize;
+        rom[dcb + 0x14..dcb + 0x16].copy_from_slice(&(table as u16).to_le_bytes());
+        rom[table] = 0x40;
+        rom[table + 1] = CONN_TABLE_HEADER_SIZE as u8;
+        rom[table + 2] = self.connectors.len() as u8;
+        rom[table + 3] = CONN_TABLE_ENTRY_SIZE as u8;
+
+        for (i, &conn_type) in self.connectors.iter().enumerate() {
+            rom[table + CONN_TABLE_HEADER_SIZE + i * CONN_TABLE_ENTRY_SIZE] = conn_type;
+        }
+    }
+}
+
+// Hex bytes from a fixture file, skipping # comments
+pub fn parse_hex_fixture(text: &str) -> Vec<u8> {
+    let hex: Vec<&str> = text.lines().filter(|line| !line.trim_start().starts_with('#')).collect();
+    crate::util::parse_hex_bytes(&hex.join(" ")).expect("fixture is not valid hex")
+}
+
+// One line per merged display, for comparing against golden output
+pub fn describe_displays(displays: &[crate::util::Display]) -> Vec<String> {
+    displays.iter()
+        .map(|display| format!("{:?} {:?} {:#x}", display.disp_type, display.dcb_entries, display.head_bitmask))
+        .collect()
+}
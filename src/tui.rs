@@ -0,0 +1,538 @@
+use std::env;
+use std::io::{self, Write};
+use colored::*;
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::tty::IsTty;
+
+use crate::edid;
+use crate::gpus::Suggestion;
+use crate::heads;
+use crate::history::History;
+use crate::lvds;
+use crate::nvidia::{self, Vbios, NVCAP_SIZE};
+use crate::session::{self, Session};
+use crate::util::{self, DisplayType};
+use crate::validate::{self, Severity};
+
+// Columns a display can be moved between, in on-screen order
+const COLUMNS: [Column; 4] = [Column::Unassigned, Column::Head0, Column::Head1, Column::TV];
+const COLUMN_WIDTH: usize = 10;
+
+const FIELDS: [Field; 8] = [Field::Mobile, Field::Version, Field::Composite, Field::ScriptBased, Field::FieldF,
+                           Field::Unknown1, Field::EdidBitness, Field::Unknown2];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    Unassigned,
+    Head0,
+    Head1,
+    TV,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Mobile,
+    Version,
+    Composite,
+    ScriptBased,
+    FieldF,
+    Unknown1,
+    EdidBitness,
+    Unknown2,
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Displays,
+    Fields,
+}
+
+#[derive(Clone, Copy)]
+enum EditTarget {
+    Field(Field),
+    RawBytes,
+    SessionPath,
+}
+
+// Text being typed in for a field or a file name
+struct Editor {
+    target: EditTarget,
+    label: String,
+    text: String,
+}
+
+struct App<'a> {
+    vbios: &'a Vbios,
+    state: &'a mut Session,
+    version_hint: &'a Suggestion,
+    field_f_hint: &'a Suggestion,
+    // Only when an EDID is attached to the panel
+    edid_bitness_hint: Option<Suggestion>,
+    focus: Focus,
+    display: usize,
+    field: usize,
+    editor: Option<Editor>,
+    messages: Vec<String>,
+    last_nvcap: Option<[u8; NVCAP_SIZE]>,
+    // Left to print the NVCAP value rather than to go back
+    print: bool,
+}
+
+// Leaves the terminal the way we found it, even if drawing fails part way through
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// The plain prompts still work on dumb terminals, when piping input in, or when asked for
+pub fn supported() -> bool {
+    let dumb = env::var("TERM").map(|term| term == "dumb").unwrap_or(false);
+    let plain = env::args().any(|arg| arg == "--plain");
+
+    !dumb && !plain && io::stdin().is_tty() && io::stdout().is_tty()
+}
+
+// Number of history entries that fit under the NVCAP preview
+const HISTORY_LINES: usize = 8;
+
+// Returns true when the user left to print the NVCAP value, which needs the normal screen
+pub fn run(vbios: &Vbios, state: &mut Session, history: &mut History<Session>,
+           version_hint: &Suggestion, field_f_hint: &Suggestion) -> io::Result<bool> {
+    let _guard = TerminalGuard::new()?;
+    let mut app = App {
+        vbios,
+        state,
+        version_hint,
+        field_f_hint,
+        edid_bitness_hint: edid::suggest_edid_bitness(&vbios.displays),
+        focus: Focus::Displays,
+        display: 0,
+        field: 0,
+        editor: None,
+        messages: vec![],
+        last_nvcap: None,
+        print: false,
+    };
+
+    loop {
+        app.draw()?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            // Undo and redo move through the history themselves, recording them would clear the redo stack
+            let moves_history = app.editor.is_none() && matches!(key.code, KeyCode::Char('u') | KeyCode::Char('r'));
+            let before = app.state.clone();
+            if !app.handle_key(key, history) {
+                break;
+            }
+
+            if !moves_history {
+                let description = session::describe_changes(&before, app.state);
+                history.record(before, app.state, description);
+            }
+        }
+    }
+
+    Ok(app.print)
+}
+
+impl Column {
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Unassigned => "None",
+            Column::Head0 => "Head 1",
+            Column::Head1 => "Head 2",
+            Column::TV => "TV",
+        }
+    }
+}
+
+impl Field {
+    fn title(&self) -> &'static str {
+        match self {
+            Field::Mobile => "Mobile",
+            Field::Version => "Version",
+            Field::Composite => "Composite",
+            Field::ScriptBased => "Script Based Power/Backlight",
+            Field::FieldF => "Field F",
+            Field::Unknown1 => "Unknown 1",
+            Field::EdidBitness => "EDID Bitness",
+            Field::Unknown2 => "Unknown 2",
+        }
+    }
+}
+
+impl<'a> App<'a> {
+    fn head(&mut self, column: Column) -> Option<&mut Vec<usize>> {
+        match column {
+            Column::Unassigned => None,
+            Column::Head0 => Some(&mut self.state.head_0),
+            Column::Head1 => Some(&mut self.state.head_1),
+            Column::TV => Some(&mut self.state.head_tv),
+        }
+    }
+
+    fn columns_of(&self, disp_idx: usize) -> Vec<Column> {
+        let mut columns = vec![];
+        if self.state.head_0.contains(&disp_idx) { columns.push(Column::Head0); }
+        if self.state.head_1.contains(&disp_idx) { columns.push(Column::Head1); }
+        if self.state.head_tv.contains(&disp_idx) { columns.push(Column::TV); }
+        if columns.is_empty() { columns.push(Column::Unassigned); }
+        columns
+    }
+
+    fn allowed(&self, disp_idx: usize, column: Column) -> bool {
+        let display = &self.vbios.displays[disp_idx];
+        match column {
+            Column::Unassigned => true,
+            Column::Head0 => display.head_bitmask & nvidia::HEAD_0_BITMASK != 0,
+            Column::Head1 => display.head_bitmask & nvidia::HEAD_1_BITMASK != 0,
+            Column::TV => display.disp_type == DisplayType::TV,
+        }
+    }
+
+    // Move the selected display to the next head it supports in the given direction
+    fn move_display(&mut self, right: bool) {
+        let disp_idx = self.display;
+        if disp_idx >= self.vbios.displays.len() {
+            return;
+        }
+
+        let current = self.columns_of(disp_idx)[0];
+        let mut pos = COLUMNS.iter().position(|&col| col == current).unwrap_or(0);
+        let target = loop {
+            if right {
+                if pos + 1 >= COLUMNS.len() { return; }
+                pos += 1;
+            } else {
+                if pos == 0 { return; }
+                pos -= 1;
+            }
+
+            if self.allowed(disp_idx, COLUMNS[pos]) {
+                break COLUMNS[pos];
+            }
+        };
+
+        for column in COLUMNS.iter() {
+            if let Some(head) = self.head(*column) {
+                head.retain(|&idx| idx != disp_idx);
+            }
+        }
+
+        if let Some(head) = self.head(target) {
+            head.push(disp_idx);
+        }
+    }
+
+    fn edit_field(&mut self) {
+        let nvcap = &mut self.state.nvcap;
+        match FIELDS[self.field] {
+            Field::Mobile => nvcap.is_mobile = !nvcap.is_mobile,
+            Field::Composite => nvcap.is_composite = !nvcap.is_composite,
+            Field::ScriptBased => nvcap.script_based_power_and_backlight = !nvcap.script_based_power_and_backlight,
+            Field::Unknown2 => self.editor = Some(Editor {
+                target: EditTarget::Field(Field::Unknown2),
+                label: "New Unknown 2 value (3 hex bytes)".to_owned(),
+                text: String::new(),
+            }),
+            field => self.editor = Some(Editor {
+                target: EditTarget::Field(field),
+                label: format!("New {} value (hex)", field.title()),
+                text: String::new(),
+            }),
+        }
+    }
+
+    // Replace the whole NVCAP value, or a single byte given as "<offset> <value>"
+    fn edit_raw_bytes(&mut self, text: &str) {
+        self.messages = match self.state.apply_raw_input(&self.vbios.displays, text) {
+            Ok(mut warnings) => {
+                warnings.insert(0, "Applied raw NVCAP bytes".to_owned());
+                warnings
+            }
+            Err(error) => vec![error],
+        };
+    }
+
+    fn finish_edit(&mut self, editor: Editor) {
+        let field = match editor.target {
+            EditTarget::Field(Field::Unknown2) => {
+                match util::parse_hex_bytes(&editor.text) {
+                    Some(bytes) if bytes.len() == self.state.nvcap.unknown_2.len() => {
+                        self.state.nvcap.unknown_2.copy_from_slice(&bytes);
+                    }
+                    _ => self.messages = vec![format!("\"{}\" is not 3 hex bytes", editor.text)],
+                }
+                return;
+            }
+            EditTarget::Field(field) => field,
+            EditTarget::RawBytes => {
+                self.edit_raw_bytes(&editor.text);
+                return;
+            }
+            EditTarget::SessionPath => {
+                self.save(&editor.text);
+                return;
+            }
+        };
+
+        let value = match u8::from_str_radix(editor.text.trim().trim_start_matches("0x"), 16) {
+            Ok(value) => value,
+            Err(_) => {
+                self.messages = vec![format!("\"{}\" is not a hex byte", editor.text)];
+                return;
+            }
+        };
+
+        match field {
+            Field::Version => self.state.nvcap.version = value,
+            Field::FieldF => self.state.nvcap.field_f = value,
+            Field::Unknown1 => self.state.nvcap.unknown_1 = value,
+            Field::EdidBitness => self.state.nvcap.edid_bitness = value,
+            _ => {}
+        }
+    }
+
+    fn save(&mut self, path: &str) {
+        let path = if path.is_empty() {
+            format!("{}.nvcap", self.state.rom_path)
+        } else {
+            path.replace("\"", "")
+        };
+
+        self.messages = match session::save(&path, self.state) {
+            Ok(_) => vec![format!("Saved session to {}", path)],
+            Err(_) => vec![format!("Unable to save session to {}", path)],
+        };
+    }
+
+    fn check(&mut self) {
+        let state = &self.state;
+        let issues = validate::check(&self.vbios.displays, &self.vbios.dcb_entries, state.nvcap.is_mobile,
+                                     &state.head_tv, &state.head_0, &state.head_1);
+
+        self.messages = issues.iter().map(|issue| match issue.severity {
+            Severity::Error => format!("{} {}", "Error:".red(), issue.message),
+            Severity::Warning => format!("{} {}", "Warning:".yellow(), issue.message),
+        }).collect();
+
+        if self.messages.is_empty() {
+            self.messages.push(format!("{} No problems found", "OK:".green()));
+        }
+    }
+
+    fn auto_layout(&mut self) {
+        if let Some(layout) = heads::solve(&self.vbios.displays, &[]).into_iter().next() {
+            self.state.head_0 = layout.head_0;
+            self.state.head_1 = layout.head_1;
+            self.messages = layout.notes;
+            self.messages.insert(0, "Applied the suggested head layout".to_owned());
+        }
+    }
+
+    // Returns false once the user wants to leave
+    fn handle_key(&mut self, key: KeyEvent, history: &mut History<Session>) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        if let Some(mut editor) = self.editor.take() {
+            match key.code {
+                KeyCode::Enter => self.finish_edit(editor),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => { editor.text.pop(); self.editor = Some(editor); }
+                KeyCode::Char(c) => { editor.text.push(c); self.editor = Some(editor); }
+                _ => self.editor = Some(editor),
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = if self.focus == Focus::Displays { Focus::Fields } else { Focus::Displays };
+            }
+            KeyCode::Up => match self.focus {
+                Focus::Displays => self.display = self.display.saturating_sub(1),
+                Focus::Fields => self.field = self.field.saturating_sub(1),
+            },
+            KeyCode::Down => match self.focus {
+                Focus::Displays => self.display = (self.display + 1).min(self.vbios.displays.len().saturating_sub(1)),
+                Focus::Fields => self.field = (self.field + 1).min(FIELDS.len() - 1),
+            },
+            KeyCode::Left if self.focus == Focus::Displays => self.move_display(false),
+            KeyCode::Right if self.focus == Focus::Displays => self.move_display(true),
+            KeyCode::Enter | KeyCode::Char(' ') if self.focus == Focus::Fields => self.edit_field(),
+            KeyCode::Char('a') => self.auto_layout(),
+            KeyCode::Char('u') => self.messages = match history.undo(self.state) {
+                Some(change) => vec![format!("Undid {}", change)],
+                None => vec!["Nothing to undo".to_owned()],
+            },
+            KeyCode::Char('r') => self.messages = match history.redo(self.state) {
+                Some(change) => vec![format!("Redid {}", change)],
+                None => vec!["Nothing to redo".to_owned()],
+            },
+            KeyCode::Char('h') => {
+                let log = history.log();
+                self.messages = log.iter().skip(log.len().saturating_sub(HISTORY_LINES)).cloned().collect();
+                self.messages.insert(0, format!("Last {} of {} changes:", self.messages.len(), log.len()));
+            }
+            KeyCode::Char('c') => self.check(),
+            KeyCode::Char('p') => {
+                self.print = true;
+                return false;
+            }
+            KeyCode::Char('e') => {
+                if let Some(display) = self.vbios.displays.get(self.display) {
+                    self.messages = vec![
+                        format!("({}) {:?}", self.display + 1, display.disp_type),
+                        format!("{} {}", "Merged:".green(), display.merge_reason),
+                        format!("{} {}", "Type:".green(), display.type_reason),
+                    ];
+
+                    if let Some(edid) = &display.edid {
+                        self.messages.push(format!("{} {}", "EDID:".green(), edid.summary()));
+                    }
+
+                    if let (DisplayType::LVDS, Some(lvds)) = (&display.disp_type, &self.vbios.lvds) {
+                        self.messages.push(format!("{}", "Panel:".green()));
+                        self.messages.extend(lvds::describe_panel(lvds));
+                    }
+                }
+            }
+            KeyCode::Char('x') => self.editor = Some(Editor {
+                target: EditTarget::RawBytes,
+                label: format!("NVCAP bytes (all {}, or \"<offset> <value>\")", NVCAP_SIZE),
+                text: String::new(),
+            }),
+            KeyCode::Char('s') => self.editor = Some(Editor {
+                target: EditTarget::SessionPath,
+                label: format!("Save session to (empty for {}.nvcap)", self.state.rom_path),
+                text: String::new(),
+            }),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let mut lines: Vec<String> = vec![];
+        let displays = &self.vbios.displays;
+
+        lines.push(format!("{} - {} (displays merged by {})", "NVCAP Calculator".cyan(), self.state.rom_path.green(),
+            self.vbios.merge.name()));
+        lines.push(String::new());
+
+        let mut title = format!("  {:<24}{:<12}", "Displays", "Supported");
+        for column in COLUMNS.iter() {
+            title += &format!("{:<width$}", column.title(), width = COLUMN_WIDTH);
+        }
+        lines.push(if self.focus == Focus::Displays { title.bright_blue().to_string() } else { title });
+
+        for (i, display) in displays.iter().enumerate() {
+            let mut heads: Vec<String> = (0..4)
+                .filter(|head| display.head_bitmask & (1 << head) != 0)
+                .map(|head| (head + 1).to_string())
+                .collect();
+            if display.disp_type == DisplayType::TV {
+                heads.push("TV".to_owned());
+            }
+
+            let columns = self.columns_of(i);
+            let mut row = format!("({}) {:?}", i + 1, display.disp_type);
+            row = format!("{:<24}{:<12}", row, heads.join(","));
+            for column in COLUMNS.iter() {
+                let mark = if columns.contains(column) { "*" } else { "" };
+                row += &format!("{:<width$}", mark, width = COLUMN_WIDTH);
+            }
+
+            if self.focus == Focus::Displays && i == self.display {
+                lines.push(format!("{} {}", ">".bright_blue(), row.reversed()));
+            } else {
+                lines.push(format!("  {}", row));
+            }
+        }
+
+        lines.push(String::new());
+        let title = "  NVCAP Fields".to_owned();
+        lines.push(if self.focus == Focus::Fields { title.bright_blue().to_string() } else { title });
+
+        let nvcap = &self.state.nvcap;
+        for (i, field) in FIELDS.iter().enumerate() {
+            let (value, hint) = match field {
+                Field::Mobile => (nvcap.is_mobile.to_string(), None),
+                Field::Version => (nvcap.version.to_string(),
+                                   Some((self.version_hint.value.to_string(), &self.version_hint.reason))),
+                Field::Composite => (nvcap.is_composite.to_string(), None),
+                Field::ScriptBased => (nvcap.script_based_power_and_backlight.to_string(), None),
+                Field::FieldF => (format!("{:#x}", nvcap.field_f),
+                                  Some((format!("{:#x}", self.field_f_hint.value), &self.field_f_hint.reason))),
+                Field::Unknown1 => (format!("{:#x}", nvcap.unknown_1), None),
+                Field::EdidBitness => (format!("{:#x}", nvcap.edid_bitness), self.edid_bitness_hint.as_ref()
+                                       .map(|hint| (format!("{:#x}", hint.value), &hint.reason))),
+                Field::Unknown2 => (format!("{:02x} {:02x} {:02x}",
+                                            nvcap.unknown_2[0], nvcap.unknown_2[1], nvcap.unknown_2[2]), None),
+            };
+
+            let row = format!("{:<30}{:<9}", field.title(), value);
+            if self.focus == Focus::Fields && i == self.field {
+                lines.push(format!("{} {}", ">".bright_blue(), row.reversed()));
+            } else {
+                lines.push(format!("  {}", row));
+            }
+
+            if let Some((suggested, reason)) = hint {
+                lines.push(format!("      {}", format!("Suggested {}: {}", suggested, reason).dimmed()));
+            }
+        }
+
+        lines.push(String::new());
+        let state = &mut *self.state;
+        nvidia::update_head_masks(&mut state.nvcap, displays, &state.head_tv, &state.head_0, &state.head_1);
+        let bytes = state.nvcap.to_bytes();
+        lines.push(format!("  {} {}", "NVCAP:".cyan(),
+            nvidia::format_nvcap_hex(&bytes, self.last_nvcap.as_ref())));
+        self.last_nvcap = Some(bytes);
+        lines.push(format!("  TV mask: {:#x}  Head 1 mask: {:#x}  Head 2 mask: {:#x}",
+            state.nvcap.dcb_tv_mask, state.nvcap.dcb_0_mask, state.nvcap.dcb_1_mask));
+        lines.push(String::new());
+
+        for message in &self.messages {
+            lines.push(format!("  {}", message));
+        }
+
+        if let Some(editor) = &self.editor {
+            lines.push(format!("  {}: {}_", editor.label, editor.text));
+        }
+
+        lines.push(String::new());
+        lines.push("  Up/Down select  Left/Right move display  Tab switch panel  Enter edit field".dimmed().to_string());
+        lines.push("  a auto layout  e explain  x raw bytes  c check  p print  u undo  r redo  h history  s save  q back".dimmed().to_string());
+
+        let mut stdout = io::stdout();
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+        }
+        stdout.flush()
+    }
+}
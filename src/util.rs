@@ -2,17 +2,44 @@ use std::{io};
 use colored::*;
 use std::io::prelude::*;
 use chrono::{Local, Timelike};
+use crate::edid::Edid;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 #[derive(PartialEq)]
 pub enum DisplayType {
     LVDS,
+    EDP,
     TV,
     Analog,
-    Digital,
     DVI,
+    TMDS,
+    HDMI,
+    DisplayPort,
+    SDI,
+    // DCB output type this tool doesn't know about
+    Unknown(u32),
 }
 
+impl DisplayType {
+    // Built in panels, which want a head to themselves
+    pub fn is_internal(&self) -> bool {
+        matches!(self, DisplayType::LVDS | DisplayType::EDP)
+    }
+
+    // DVI and HDMI are both driven by a TMDS link
+    pub fn is_tmds(&self) -> bool {
+        matches!(self, DisplayType::DVI | DisplayType::TMDS | DisplayType::HDMI)
+    }
+
+    // No analog signal at all, DVI-I and unknown outputs get the benefit of the doubt
+    pub fn is_digital_only(&self) -> bool {
+        matches!(self, DisplayType::LVDS | DisplayType::EDP | DisplayType::TMDS | DisplayType::HDMI
+            | DisplayType::DisplayPort | DisplayType::SDI)
+    }
+}
+
+#[derive(Debug)]
 pub enum NVErrors {
     FileNotFound,
     Corrupted,
@@ -22,17 +49,61 @@ pub struct Display {
     pub disp_type: DisplayType,
     pub dcb_entries: Vec<u8>,
     pub head_bitmask: u32,
+    // Why these DCB entries became one display, and why it got its type
+    pub merge_reason: String,
+    pub type_reason: String,
+    // Attached by the user to check the monitor against the output
+    pub edid: Option<Edid>,
+}
+
+impl Display {
+    // How messages refer to the display at `disp_idx`, numbered from 1 like the menus, ie. "Display (1) LVDS"
+    pub fn label(&self, disp_idx: usize) -> String {
+        format!("Display ({}) {:?}", disp_idx + 1, self.disp_type)
+    }
 }
 
-pub fn read_uint_16_le(rom: &Vec<u8>, offset: usize) -> u16 {
-    return ((rom[offset] as u16)) + ((rom[offset + 1] as u16) << 8);
+pub fn read_uint_16_le(rom: &[u8], offset: usize) -> u16 {
+    (rom[offset] as u16) + ((rom[offset + 1] as u16) << 8)
 }
 
-pub fn read_uint_32_le(rom: &Vec<u8>, offset: usize) -> u32 {
-    return (rom[offset] as u32) + 
+pub fn read_uint_32_le(rom: &[u8], offset: usize) -> u32 {
+    (rom[offset] as u32) + 
            ((rom[offset + 1] as u32) << 8) + 
            ((rom[offset + 2] as u32) << 16) + 
-           ((rom[offset + 3] as u32) << 24);
+           ((rom[offset + 3] as u32) << 24)
+}
+
+// CRC-32 (IEEE), used to tell if a ROM changed since it was last opened
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffffffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+// Parse hex bytes typed in by the user, ie. "05 00 01", "5 0 1" or "0x050001"
+pub fn parse_hex_bytes(input: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![];
+    for word in input.split_whitespace() {
+        let digits = word.trim_start_matches("0x");
+        let digits = if digits.len() == 1 { format!("0{}", digits) } else { digits.to_owned() };
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return None;
+        }
+
+        for i in (0..digits.len()).step_by(2) {
+            bytes.push(u8::from_str_radix(digits.get(i..i + 2)?, 16).ok()?);
+        }
+    }
+
+    (!bytes.is_empty()).then_some(bytes)
 }
 
 pub fn press_any_key() {
@@ -76,12 +147,12 @@ pub fn header() {
     println!("{}", "+--------------------------+".green());
     println!("{}{}{}", "|".green(), "     NVCAP Calculator     ".cyan(), "|".green());
     println!("{}", "+--------------------------+".green());
-    println!("");
+    println!();
 }
 
-pub fn is_mobile(displays: &Vec<Display>) -> bool {
+pub fn is_mobile(displays: &[Display]) -> bool {
     for disp in displays {
-        if disp.disp_type == DisplayType::LVDS {
+        if disp.disp_type.is_internal() {
             return true;
         }
     }
@@ -89,7 +160,7 @@ pub fn is_mobile(displays: &Vec<Display>) -> bool {
     false
 }
 
-pub fn has_tv(displays: &Vec<Display>) -> bool {
+pub fn has_tv(displays: &[Display]) -> bool {
     for disp in displays {
         if disp.disp_type == DisplayType::TV {
             return true;
@@ -97,4 +168,20 @@ pub fn has_tv(displays: &Vec<Display>) -> bool {
     }
 
     false
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_bytes() {
+        assert_eq!(parse_hex_bytes("05 00 01"), Some(vec![0x05, 0x00, 0x01]));
+        assert_eq!(parse_hex_bytes("0x050001"), Some(vec![0x05, 0x00, 0x01]));
+        assert_eq!(parse_hex_bytes("1 2"), Some(vec![0x01, 0x02]));
+        assert_eq!(parse_hex_bytes("a bc"), Some(vec![0x0a, 0xbc]));
+        assert_eq!(parse_hex_bytes("abc"), None);
+        assert_eq!(parse_hex_bytes("0x"), None);
+        assert_eq!(parse_hex_bytes("zz"), None);
+        assert_eq!(parse_hex_bytes("  "), None);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::nvidia::{self, DcbEntry};
+use crate::util::{Display, DisplayType};
+
+// DCB I2C index meaning the output has no DDC line
+const NO_I2C_PORT: u32 = 0xf;
+
+#[derive(PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+fn error(message: String) -> Issue {
+    Issue { severity: Severity::Error, message }
+}
+
+fn warning(message: String) -> Issue {
+    Issue { severity: Severity::Warning, message }
+}
+
+fn i2c_ports(display: &Display, dcb_entries: &[DcbEntry]) -> Vec<u32> {
+    let mut ports: Vec<u32> = dcb_entries.iter()
+        .filter(|entry| display.dcb_entries.contains(&entry.entry))
+        .map(|entry| entry.edid_port)
+        .filter(|&port| port != NO_I2C_PORT)
+        .collect();
+
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+// Check head assignments for mistakes before they end up in an NVCAP value
+pub fn check(displays: &[Display], dcb_entries: &[DcbEntry], is_mobile: bool,
+             head_tv: &[usize], head_0: &[usize], head_1: &[usize]) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = vec![];
+    let heads: [(&str, &[usize], u32); 3] = [
+        ("TV", head_tv, 0),
+        ("1", head_0, nvidia::HEAD_0_BITMASK),
+        ("2", head_1, nvidia::HEAD_1_BITMASK),
+    ];
+
+    for (i, display) in displays.iter().enumerate() {
+        let assigned: Vec<&str> = heads.iter()
+            .filter(|(_, members, _)| members.contains(&i))
+            .map(|(name, _, _)| *name)
+            .collect();
+
+        if let Some(edid) = display.edid.as_ref().filter(|edid| !edid.digital) {
+            if display.disp_type.is_digital_only() {
+                issues.push(error(format!("{} is a digital-only output but its EDID ({}) is an analog monitor",
+                    display.label(i), edid.summary())));
+            }
+        }
+
+        if assigned.is_empty() {
+            issues.push(warning(format!("{} is not on any head and will not work", display.label(i))));
+        } else if assigned.len() > 1 {
+            issues.push(error(format!("{} is on multiple heads ({})", display.label(i), assigned.join(", "))));
+        }
+    }
+
+    for (name, members, bitmask) in heads.iter() {
+        for &disp_idx in members.iter() {
+            let display = &displays[disp_idx];
+            if *name == "TV" {
+                if display.disp_type != DisplayType::TV {
+                    issues.push(error(format!("{} is on the TV head but is not a TV output",
+                        displays[disp_idx].label(disp_idx))));
+                }
+            } else if display.head_bitmask & bitmask == 0 {
+                issues.push(error(format!("{} does not support head {}", displays[disp_idx].label(disp_idx), name)));
+            }
+        }
+
+        // Outputs sharing an I2C port can't tell which one a monitor is plugged into
+        for (i, &a) in members.iter().enumerate() {
+            let ports_a = i2c_ports(&displays[a], dcb_entries);
+            for &b in members.iter().skip(i + 1) {
+                if let Some(port) = i2c_ports(&displays[b], dcb_entries).iter().find(|port| ports_a.contains(port)) {
+                    issues.push(warning(format!("{} and {} share I2C port {} on head {}",
+                        displays[a].label(a), displays[b].label(b), port, name)));
+                }
+            }
+        }
+    }
+
+    if head_0.is_empty() {
+        issues.push(warning("Head 1 has no displays".to_owned()));
+    }
+
+    if head_1.is_empty() {
+        issues.push(warning("Head 2 has no displays".to_owned()));
+    }
+
+    if is_mobile {
+        for (i, display) in displays.iter().enumerate() {
+            if display.disp_type.is_internal() && !head_0.contains(&i) {
+                issues.push(warning(format!("{} is the internal panel but is not on head 1", display.label(i))));
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvidia::parse_rom;
+    use crate::test_support::*;
+
+    // LVDS on head 1 only, a TMDS and a CRT output on either head, and a TV output.
+    // The TMDS and CRT outputs share I2C port 1.
+    fn laptop() -> nvidia::Vbios {
+        let rom = RomBuilder::new()
+            .entry(dcb_entry(LVDS, 0, 1, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 1, 1))
+            .entry(dcb_entry(CRT, 1, 3, 2, 2))
+            .entry(dcb_entry(TV, 2, 3, 3, 3))
+            .build();
+        parse_rom(&rom).unwrap()
+    }
+
+    fn messages(head_tv: &[usize], head_0: &[usize], head_1: &[usize]) -> Vec<(bool, String)> {
+        let vbios = laptop();
+        check(&vbios.displays, &vbios.dcb_entries, true, head_tv, head_0, head_1).into_iter()
+            .map(|issue| (issue.severity == Severity::Error, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_good_layout() {
+        assert!(messages(&[3], &[0, 2], &[1]).is_empty());
+    }
+
+    #[test]
+    fn finds_displays_on_multiple_heads() {
+        assert!(messages(&[3], &[0, 1], &[1, 2]).contains(&(true, "Display (2) TMDS is on multiple heads (1, 2)".to_owned())));
+    }
+
+    #[test]
+    fn finds_unsupported_heads() {
+        let issues = messages(&[1], &[2], &[0]);
+        assert!(issues.contains(&(true, "Display (1) LVDS does not support head 2".to_owned())));
+        assert!(issues.contains(&(true, "Display (2) TMDS is on the TV head but is not a TV output".to_owned())));
+    }
+
+    #[test]
+    fn finds_unassigned_displays() {
+        let issues = messages(&[3], &[0], &[1]);
+        assert!(issues.contains(&(false, "Display (3) Analog is not on any head and will not work".to_owned())));
+        assert!(messages(&[3], &[], &[]).contains(&(false, "Head 1 has no displays".to_owned())));
+    }
+
+    #[test]
+    fn finds_shared_i2c_ports() {
+        let issues = messages(&[3], &[0], &[1, 2]);
+        assert!(issues.contains(&(false, "Display (2) TMDS and Display (3) Analog share I2C port 1 on head 2".to_owned())));
+        assert!(!messages(&[3], &[0, 2], &[1]).iter().any(|(_, message)| message.contains("I2C")));
+
+        // Each port is listed once, even when the display's entries alternate between them
+        let vbios = parse_rom(&RomBuilder::new()
+            .entry(dcb_entry(TMDS, 1, 3, 0, 0))
+            .entry(dcb_entry(CRT, 2, 3, 0, 0))
+            .entry(dcb_entry(TMDS, 1, 3, 0, 0))
+            .build()).unwrap();
+        assert_eq!(i2c_ports(&vbios.displays[0], &vbios.dcb_entries), [1, 2]);
+    }
+
+    #[test]
+    fn finds_the_panel_off_head_1() {
+        let issues = messages(&[3], &[1], &[0, 2]);
+        assert!(issues.contains(&(false, "Display (1) LVDS is the internal panel but is not on head 1".to_owned())));
+    }
+}